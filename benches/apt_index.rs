@@ -0,0 +1,84 @@
+//! APTの`Packages`インデックスの解析のベンチマーク
+//!
+//! `fixtures/Packages.xz`はDebian bookwormのセキュリティアップデートの
+//! `Packages`の先頭2000段落です。
+
+use chrono::Local;
+use criterion::{
+    Criterion, Throughput, criterion_group, criterion_main,
+};
+use ipm::modules::repo::types::apt;
+use ipm::utils::deb822::{self, Fields};
+use ipm::utils::www::ToURL;
+use std::hint::black_box;
+use std::io::Read;
+use std::path::Path;
+
+fn fixture() -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("benches/fixtures/Packages.xz");
+    let file = std::fs::File::open(path).unwrap();
+    let mut content = String::new();
+    xz2::read::XzDecoder::new(file)
+        .read_to_string(&mut content)
+        .unwrap();
+    content
+}
+
+fn bench_index(c: &mut Criterion) {
+    let index = fixture();
+    let root = "http://deb.debian.org/debian-security"
+        .to_url()
+        .unwrap();
+    let mut group = c.benchmark_group("apt_index");
+    group.throughput(Throughput::Bytes(index.len() as u64));
+    group.bench_function("deb822::parse", |b| {
+        b.iter(|| {
+            deb822::parse(black_box(&index)).unwrap().len()
+        })
+    });
+    group.bench_function("deb822::stanzas", |b| {
+        b.iter(|| {
+            deb822::stanzas(black_box(&index))
+                .filter(|stanza| {
+                    stanza
+                        .as_ref()
+                        .unwrap()
+                        .field("Description")
+                        .is_some()
+                })
+                .count()
+        })
+    });
+    group.bench_function("apt::parse_packages", |b| {
+        b.iter(|| {
+            let mut diagnostics = Vec::new();
+            apt::parse_packages(
+                black_box(&index),
+                "bench",
+                &root,
+                Local::now(),
+                &mut diagnostics,
+            )
+            .len()
+        })
+    });
+    group.bench_function("apt::read_packages", |b| {
+        b.iter(|| {
+            let mut diagnostics = Vec::new();
+            apt::read_packages(
+                black_box(index.as_bytes()),
+                "bench",
+                &root,
+                Local::now(),
+                &mut diagnostics,
+            )
+            .unwrap()
+            .len()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_index);
+criterion_main!(benches);
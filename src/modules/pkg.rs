@@ -1,6 +1,9 @@
 use cmd_arg::cmd_arg;
 use ipak::modules::pkg as ipak_pkg;
 pub mod convert;
+pub mod manifest;
+pub mod relation;
+pub mod verify;
 pub fn pkg(
     args: Vec<&cmd_arg::Option>,
 ) -> Result<(), std::io::Error> {
@@ -13,6 +16,7 @@ pub fn pkg(
     let sub_args = args[1..].to_vec();
     match sub_cmd.as_str() {
         "convert" => convert::convert(sub_args),
+        "verify" => verify::verify(sub_args),
         _ => ipak_pkg::pkg(args),
     }
 }
@@ -1,7 +1,574 @@
+use super::manifest::Manifest;
+use super::relation::ExtraRelationData;
+use crate::modules::repo::types::apt;
 use cmd_arg::cmd_arg;
+use ipak::utils::color::colorize::*;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+mod alpine;
+mod arch;
+mod cpio;
+mod deb;
+mod ipak_archive;
+mod pkginfo;
+mod rpm;
+mod safe_path;
+
+/// 変換元のパッケージ形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputFormat {
+    Deb,
+    Rpm,
+    Arch,
+    Alpine,
+    Ipak,
+}
+
+impl InputFormat {
+    fn detect(path: &Path) -> Result<Self, io::Error> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name.ends_with(".deb") {
+            Ok(Self::Deb)
+        } else if name.ends_with(".rpm") {
+            Ok(Self::Rpm)
+        } else if name.contains(".pkg.tar") {
+            Ok(Self::Arch)
+        } else if name.ends_with(".apk") {
+            Ok(Self::Alpine)
+        } else if name.ends_with(".ipak") {
+            Ok(Self::Ipak)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported package format: {}", name),
+            ))
+        }
+    }
+}
+
+/// 変換先のパッケージ形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Ipak,
+    Deb,
+}
+
+impl FromStr for OutputFormat {
+    type Err = io::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ipak" => Ok(Self::Ipak),
+            "deb" => Ok(Self::Deb),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported output format: {}", s),
+            )),
+        }
+    }
+}
+
+struct ConvertOptions {
+    inputs: Vec<PathBuf>,
+    output_dir: PathBuf,
+    to: OutputFormat,
+}
+
+impl ConvertOptions {
+    fn from_args(
+        args: Vec<&cmd_arg::Option>,
+    ) -> Result<Self, io::Error> {
+        let mut opts = Self {
+            inputs: vec![],
+            output_dir: env::current_dir()?,
+            to: OutputFormat::default(),
+        };
+        for arg in args {
+            match arg.opt_str.as_str() {
+                "--output" | "-o" => {
+                    if arg.opt_values.len() == 1 {
+                        opts.output_dir = PathBuf::from(
+                            arg.opt_values.first().unwrap(),
+                        );
+                    }
+                }
+                "--to" | "-t" => {
+                    if arg.opt_values.len() == 1 {
+                        opts.to = OutputFormat::from_str(
+                            arg.opt_values.first().unwrap(),
+                        )?;
+                    }
+                }
+                _ => {
+                    if arg.opt_type
+                        == cmd_arg::OptionType::Simple
+                    {
+                        opts.inputs
+                            .push(PathBuf::from(&arg.opt_str));
+                    }
+                }
+            }
+        }
+        if opts.inputs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No package file specified",
+            ));
+        }
+        Ok(opts)
+    }
+}
 
 pub fn convert(
-    _args: Vec<&cmd_arg::Option>,
-) -> Result<(), std::io::Error> {
+    args: Vec<&cmd_arg::Option>,
+) -> Result<(), io::Error> {
+    let opts = ConvertOptions::from_args(args)?;
+    fs::create_dir_all(&opts.output_dir)?;
+    for input in &opts.inputs {
+        let output =
+            convert_file(input, &opts.output_dir, opts.to)?;
+        println!(
+            "{}: {} -> {}",
+            "Converted".green().bold(),
+            input.display(),
+            output.display()
+        );
+    }
     Ok(())
 }
+
+/// パッケージファイルを`to`の形式に変換し、出力先のパスを返します。
+pub fn convert_file(
+    input: &Path,
+    output_dir: &Path,
+    to: OutputFormat,
+) -> Result<PathBuf, io::Error> {
+    let format = InputFormat::detect(input)?;
+    if matches!(
+        (format, to),
+        (InputFormat::Deb, OutputFormat::Deb)
+            | (InputFormat::Ipak, OutputFormat::Ipak)
+    ) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is already in the requested format",
+                input.display()
+            ),
+        ));
+    }
+    let stage_dir = stage_dir(input);
+    if stage_dir.exists() {
+        fs::remove_dir_all(&stage_dir)?;
+    }
+    fs::create_dir_all(&stage_dir)?;
+    let result = (|| -> Result<PathBuf, io::Error> {
+        let package_data = match format {
+            InputFormat::Deb => deb::extract(input, &stage_dir),
+            InputFormat::Rpm => rpm::extract(input, &stage_dir),
+            InputFormat::Arch => {
+                arch::extract(input, &stage_dir)
+            }
+            InputFormat::Alpine => {
+                alpine::extract(input, &stage_dir)
+            }
+            InputFormat::Ipak => {
+                ipak_archive::extract(input, &stage_dir)
+                    .map_err(anyhow::Error::from)
+            }
+        }
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Failed to read {}: {}",
+                    input.display(),
+                    e
+                ),
+            )
+        })?;
+        let extra = extra_relations(format, &stage_dir)?;
+        match to {
+            OutputFormat::Ipak => {
+                ipak_archive::write_metadata(
+                    &stage_dir,
+                    &package_data,
+                )?;
+                extra.write(
+                    &stage_dir.join("ipak/relations.yaml"),
+                )?;
+                write_manifest(&stage_dir)?;
+                let root_name = format!(
+                    "{}-{}",
+                    package_data.about.package.name,
+                    package_data.about.package.version
+                );
+                let output = output_dir
+                    .join(format!("{}.ipak", root_name));
+                ipak_archive::pack(
+                    &stage_dir, &root_name, &output,
+                )?;
+                Ok(output)
+            }
+            OutputFormat::Deb => {
+                let output = output_dir
+                    .join(deb::file_name(&package_data));
+                deb::build(
+                    &stage_dir,
+                    &package_data,
+                    &extra,
+                    &output,
+                )
+                .map_err(|e| io::Error::other(e.to_string()))?;
+                Ok(output)
+            }
+        }
+    })();
+    // 作業ディレクトリは成功・失敗に関わらず削除する。
+    // 削除に失敗しても変換の結果(エラー)を優先して返す
+    let _ = fs::remove_dir_all(&stage_dir);
+    result
+}
+
+/// 展開済みのパッケージから、ipakのRelationDataで表現できない
+/// 依存関係(Pre-Depends, Breaks, Replaces, Enhances)を読み取ります。
+fn extra_relations(
+    format: InputFormat,
+    stage_dir: &Path,
+) -> Result<ExtraRelationData, io::Error> {
+    let control_dir = stage_dir.join("control");
+    let result = match format {
+        InputFormat::Deb => {
+            let control =
+                fs::read_to_string(control_dir.join("control"))?;
+            apt::parse_control_file(&control).and_then(|data| {
+                apt::to_extra_relation_data(&data)
+            })
+        }
+        InputFormat::Arch | InputFormat::Alpine => {
+            let content = fs::read_to_string(
+                control_dir.join(".PKGINFO"),
+            )?;
+            pkginfo::PkgInfo::parse(&content)
+                .to_extra_relation_data()
+        }
+        InputFormat::Ipak => {
+            return ExtraRelationData::from_file(
+                &stage_dir.join("ipak/relations.yaml"),
+            );
+        }
+        // RPMには対応する依存関係がない
+        InputFormat::Rpm => Ok(ExtraRelationData::default()),
+    };
+    result.map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+    })
+}
+
+/// `data/`以下のファイルのチェックサムを`ipak/manifest.yaml`に書き出します。
+/// debから変換した場合は`control/md5sums`の内容も照合して含めます。
+fn write_manifest(stage_dir: &Path) -> Result<(), io::Error> {
+    let data_dir = stage_dir.join("data");
+    if !data_dir.is_dir() {
+        return Ok(());
+    }
+    let md5sums =
+        fs::read_to_string(stage_dir.join("control/md5sums"))
+            .ok();
+    Manifest::generate(&data_dir, md5sums.as_deref())?
+        .write(&stage_dir.join("ipak/manifest.yaml"))
+}
+
+/// 展開作業用の一時ディレクトリ
+fn stage_dir(input: &Path) -> PathBuf {
+    let file_name = input
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    env::temp_dir().join(format!(
+        "ipm-convert-{}-{}",
+        process::id(),
+        file_name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+    use std::io::Read;
+
+    #[test]
+    fn test_convert_deb_to_ipak() -> Result<(), io::Error> {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tmp/hello_2.10-3build2_amd64.deb");
+        let output_dir = TempDir::new("convert-deb")?;
+        let output = convert_file(
+            &input,
+            output_dir.path(),
+            OutputFormat::Ipak,
+        )?;
+        assert_eq!(
+            output.file_name().unwrap(),
+            "hello-2.10-3build2.ipak"
+        );
+
+        let mut archive =
+            zip::ZipArchive::new(fs::File::open(&output)?)?;
+        for name in [
+            "hello-2.10-3build2/debian-binary",
+            "hello-2.10-3build2/control/control",
+            "hello-2.10-3build2/data/usr/bin/hello",
+            "hello-2.10-3build2/ipak/scripts/install.sh",
+        ] {
+            assert!(
+                archive.by_name(name).is_ok(),
+                "{} should be packed",
+                name
+            );
+        }
+        let mut project_yaml = String::new();
+        archive
+            .by_name("hello-2.10-3build2/ipak/project.yaml")?
+            .read_to_string(&mut project_yaml)?;
+        assert!(project_yaml.contains("name: hello"));
+        assert!(project_yaml.contains("libc6"));
+        // 詳細な説明も切り捨てずに保持する
+        assert!(project_yaml.contains("Seriously, though"));
+        let mut manifest_yaml = String::new();
+        archive
+            .by_name("hello-2.10-3build2/ipak/manifest.yaml")?
+            .read_to_string(&mut manifest_yaml)?;
+        assert!(manifest_yaml.contains("path: usr/bin/hello"));
+        assert!(
+            manifest_yaml.contains(
+                "md5: 8efcd3c0a33141a2c4b4cdee10a0c3f7"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_rpm_to_ipak() -> Result<(), io::Error> {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tmp/hello-1.2-3.el9.x86_64.rpm");
+        let output_dir = TempDir::new("convert-rpm")?;
+        let output = convert_file(
+            &input,
+            output_dir.path(),
+            OutputFormat::Ipak,
+        )?;
+        assert_eq!(
+            output.file_name().unwrap(),
+            "hello-1.2-3.el9.ipak"
+        );
+
+        let mut archive =
+            zip::ZipArchive::new(fs::File::open(&output)?)?;
+        assert!(
+            archive
+                .by_name("hello-1.2-3.el9/data/usr/bin/hello")
+                .is_ok()
+        );
+        let mut project_yaml = String::new();
+        archive
+            .by_name("hello-1.2-3.el9/ipak/project.yaml")?
+            .read_to_string(&mut project_yaml)?;
+        assert!(project_yaml.contains("name: hello"));
+        assert!(project_yaml.contains("1.2-3.el9"));
+        assert!(project_yaml.contains("glibc"));
+        Ok(())
+    }
+
+    /// `.PKGINFO`を持つパッケージをipakに変換し、`project.yaml`と
+    /// フックから生成された`control/postinst`の内容を返します。
+    fn convert_pkginfo_package(
+        file_name: &str,
+        root_name: &str,
+    ) -> Result<(String, String), io::Error> {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tmp")
+            .join(file_name);
+        let output_dir = TempDir::new("convert-pkginfo")?;
+        let output = convert_file(
+            &input,
+            output_dir.path(),
+            OutputFormat::Ipak,
+        )?;
+        assert_eq!(
+            output.file_name().unwrap().to_string_lossy(),
+            format!("{}.ipak", root_name)
+        );
+        let mut archive =
+            zip::ZipArchive::new(fs::File::open(&output)?)?;
+        assert!(
+            archive
+                .by_name(&format!(
+                    "{}/data/usr/bin/hello",
+                    root_name
+                ))
+                .is_ok()
+        );
+        let mut read =
+            |name: &str| -> Result<String, io::Error> {
+                let mut content = String::new();
+                archive
+                    .by_name(&format!("{}/{}", root_name, name))?
+                    .read_to_string(&mut content)?;
+                Ok(content)
+            };
+        Ok((
+            read("ipak/project.yaml")?,
+            read("control/postinst")?,
+        ))
+    }
+
+    #[test]
+    fn test_convert_arch_to_ipak() -> Result<(), io::Error> {
+        let (project_yaml, postinst) = convert_pkginfo_package(
+            "hello-2.0-1-x86_64.pkg.tar.xz",
+            "hello-1:2.0-1",
+        )?;
+        assert!(project_yaml.contains("name: hello"));
+        assert!(project_yaml.contains("1:2.0-1"));
+        assert!(project_yaml.contains("glibc"));
+        // .INSTALLの関数がpostinstに埋め込まれる
+        assert!(postinst.starts_with("#!/bin/bash"));
+        assert!(postinst.contains("post_install() {"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_alpine_to_ipak() -> Result<(), io::Error> {
+        let (project_yaml, postinst) = convert_pkginfo_package(
+            "hello-2.38-r3.apk",
+            "hello-2.38-r3",
+        )?;
+        assert!(project_yaml.contains("name: hello"));
+        assert!(project_yaml.contains("2.38-r3"));
+        assert!(project_yaml.contains("hello-legacy"));
+        assert!(postinst.contains("post_install() ("));
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_ipak_to_deb() -> Result<(), io::Error> {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tmp/hello-2.10-3build2.ipak");
+        let output_dir = TempDir::new("convert-ipak")?;
+        let output = convert_file(
+            &input,
+            output_dir.path(),
+            OutputFormat::Deb,
+        )?;
+        assert_eq!(
+            output.file_name().unwrap(),
+            "hello_2.10-3build2_amd64.deb"
+        );
+
+        // 生成したdebを再度変換し、controlの内容が保たれていることを確認
+        let stage = output_dir.path().join("stage");
+        fs::create_dir_all(&stage)?;
+        let package_data = deb::extract(&output, &stage)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        assert_eq!(package_data.about.package.name, "hello");
+        assert_eq!(package_data.architecture, vec!["amd64"]);
+        assert_eq!(
+            package_data.relation.depend[0][0].name,
+            "libc6"
+        );
+        assert!(stage.join("data/usr/bin/hello").is_file());
+        Ok(())
+    }
+
+    /// debのメンテナスクリプトが、install.sh/remove.sh/purge.shから
+    /// dpkgと同じ順序と引数で呼び出されることを確認します。
+    #[test]
+    fn test_deb_maintainer_scripts() -> Result<(), io::Error> {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tmp/hello-scripts_1.0-1_all.deb");
+        let work_dir = TempDir::new("maintscripts")?;
+        let work_dir = work_dir.path();
+        let output =
+            convert_file(&input, work_dir, OutputFormat::Ipak)?;
+        let package_dir = work_dir.join("package");
+        ipak_archive::extract(&output, &package_dir)?;
+        let root = work_dir.join("root");
+        let log = work_dir.join("scripts.log");
+        let state_dir =
+            root.join("var/lib/ipak/deb-compat/hello-scripts");
+        // 各スクリプトの実行で記録された行を返します
+        let run =
+            |script: &str| -> Result<Vec<String>, io::Error> {
+                let _ = fs::remove_file(&log);
+                let status = process::Command::new("sh")
+                    .arg(format!("ipak/scripts/{}", script))
+                    .current_dir(&package_dir)
+                    .env("IPAK_INSTALL_MODE", "global")
+                    .env("IPAK_REMOVE_MODE", "global")
+                    .env("IPAK_PURGE_MODE", "global")
+                    .env("IPAK_PROJECT_NAME", "hello-scripts")
+                    .env("IPAK_PROJECT_VERSION", "1.0-1")
+                    .env("IPAK_DEB_COMPAT_ROOT", &root)
+                    .env("IPM_SCRIPT_LOG", &log)
+                    .stdout(process::Stdio::null())
+                    .status()?;
+                assert!(status.success(), "{} failed", script);
+                Ok(fs::read_to_string(&log)
+                    .unwrap_or_default()
+                    .lines()
+                    .map(str::to_string)
+                    .collect())
+            };
+
+        assert_eq!(
+            run("install.sh")?,
+            ["preinst install", "postinst configure"]
+        );
+        assert!(root.join("etc/hello-scripts.conf").is_file());
+        // 同じバージョンの再インストールはアップグレードとして扱う
+        assert_eq!(
+            run("install.sh")?,
+            [
+                "prerm upgrade 1.0-1",
+                "preinst upgrade 1.0-1",
+                "postrm upgrade 1.0-1",
+                "postinst configure 1.0-1",
+            ]
+        );
+        assert_eq!(
+            run("remove.sh")?,
+            ["prerm remove", "postrm remove"]
+        );
+        // removeでは設定ファイルと状態が残る
+        assert!(root.join("etc/hello-scripts.conf").is_file());
+        assert!(
+            !root
+                .join("usr/share/doc/hello-scripts/README")
+                .exists()
+        );
+        assert!(state_dir.is_dir());
+        // 設定ファイルだけが残った状態からの再インストール
+        assert_eq!(
+            run("install.sh")?,
+            [
+                "preinst install 1.0-1",
+                "postinst configure 1.0-1"
+            ]
+        );
+        assert_eq!(
+            run("purge.sh")?,
+            ["prerm remove", "postrm remove", "postrm purge"]
+        );
+        assert!(!root.join("etc/hello-scripts.conf").exists());
+        assert!(!state_dir.exists());
+        Ok(())
+    }
+}
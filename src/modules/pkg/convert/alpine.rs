@@ -0,0 +1,31 @@
+use super::pkginfo::{self, PkgInfo};
+use anyhow::{Result, anyhow};
+use flate2::read::MultiGzDecoder;
+use ipak::modules::pkg::PackageData;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Alpine Linuxのパッケージ(.apk)を`dest`に展開し、
+/// `.PKGINFO`からPackageDataを生成します。
+///
+/// .apk(v2)は署名・control・dataの3つのgzipストリームを連結したもので、
+/// 各ストリームは終端ブロックを持たないtarのため、続けて1つのtarとして読めます。
+pub fn extract(
+    apk_path: &Path,
+    dest: &Path,
+) -> Result<PackageData> {
+    let mut reader = BufReader::new(File::open(apk_path)?);
+    if reader.fill_buf()?.starts_with(b"ADB") {
+        return Err(anyhow!(
+            "apk v3 (ADB) packages are not supported"
+        ));
+    }
+    let mut archive =
+        tar::Archive::new(MultiGzDecoder::new(reader));
+    archive.set_ignore_zeros(true);
+    let pkginfo = pkginfo::unpack(archive, dest)?;
+    let pkginfo = PkgInfo::parse(&pkginfo);
+    pkginfo.write_maintainer_scripts(dest)?;
+    pkginfo.to_package_data()
+}
@@ -0,0 +1,22 @@
+use super::pkginfo::{self, PkgInfo};
+use crate::utils::compression;
+use anyhow::Result;
+use ipak::modules::pkg::PackageData;
+use std::fs::File;
+use std::path::Path;
+
+/// Arch Linuxのパッケージ(.pkg.tar.zst等)を`dest`に展開し、
+/// `.PKGINFO`からPackageDataを生成します。
+pub fn extract(
+    pkg_path: &Path,
+    dest: &Path,
+) -> Result<PackageData> {
+    let name = pkg_path.to_string_lossy();
+    let decoder =
+        compression::decoder(&name, File::open(pkg_path)?)?;
+    let pkginfo =
+        pkginfo::unpack(tar::Archive::new(decoder), dest)?;
+    let pkginfo = PkgInfo::parse(&pkginfo);
+    pkginfo.write_maintainer_scripts(dest)?;
+    pkginfo.to_package_data()
+}
@@ -0,0 +1,248 @@
+use super::safe_path;
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::{Component, Path, PathBuf};
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// newc形式(SVR4)のcpioアーカイブを`dest`に展開します。
+///
+/// ハードリンクはデータを持つ最後のエントリでまとめて書き出されるため、
+/// 同じinodeを持つエントリを記録しておき、データの出現時に全て作成します。
+pub fn unpack<R: Read>(
+    mut reader: R,
+    dest: &Path,
+) -> Result<()> {
+    let mut hardlinks: HashMap<u32, Vec<PathBuf>> =
+        HashMap::new();
+    let mut offset: usize = 0;
+    loop {
+        let mut header = [0u8; 110];
+        reader.read_exact(&mut header)?;
+        offset += header.len();
+        let magic = &header[..6];
+        if magic != b"070701" && magic != b"070702" {
+            return Err(anyhow!("Invalid cpio header magic"));
+        }
+        let field = |index: usize| -> Result<u32> {
+            let start = 6 + index * 8;
+            let hex =
+                std::str::from_utf8(&header[start..start + 8])?;
+            Ok(u32::from_str_radix(hex, 16)?)
+        };
+        let ino = field(0)?;
+        let mode = field(1)?;
+        let nlink = field(4)?;
+        let file_size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let mut name = vec![0u8; name_size];
+        reader.read_exact(&mut name)?;
+        offset += name_size;
+        skip_padding(&mut reader, &mut offset)?;
+        let name = String::from_utf8_lossy(&name)
+            .trim_end_matches('\0')
+            .to_string();
+        if name == "TRAILER!!!" {
+            break;
+        }
+
+        let mut data = reader.by_ref().take(file_size as u64);
+        if let Some(path) = safe_join(dest, &name) {
+            match mode & S_IFMT {
+                S_IFDIR => {
+                    safe_path::prepare(dest, &path)?;
+                    fs::create_dir_all(&path)?;
+                    fs::set_permissions(
+                        &path,
+                        fs::Permissions::from_mode(
+                            mode & 0o7777,
+                        ),
+                    )?;
+                }
+                S_IFLNK => {
+                    if file_size as u64
+                        > safe_path::MAX_LINK_TARGET
+                    {
+                        return Err(anyhow!(
+                            "Symbolic link target of '{}' is too long",
+                            name
+                        ));
+                    }
+                    let mut target = Vec::new();
+                    data.read_to_end(&mut target)?;
+                    safe_path::prepare(dest, &path)?;
+                    if path.exists() {
+                        fs::remove_file(&path)?;
+                    }
+                    symlink(
+                        String::from_utf8_lossy(&target)
+                            .as_ref(),
+                        &path,
+                    )?;
+                }
+                S_IFREG => {
+                    let paths = if nlink > 1 {
+                        let links =
+                            hardlinks.entry(ino).or_default();
+                        links.push(path);
+                        // データは最後のエントリで渡される
+                        if file_size == 0 {
+                            vec![]
+                        } else {
+                            std::mem::take(links)
+                        }
+                    } else {
+                        vec![path]
+                    };
+                    if !paths.is_empty() {
+                        write_file(
+                            &mut data, dest, &paths, mode,
+                        )?;
+                    }
+                }
+                // デバイスファイルやFIFOは変換の対象外
+                _ => {}
+            }
+        }
+        // 書き出さなかったデータを読み飛ばす
+        io::copy(&mut data, &mut io::sink())?;
+        if data.limit() > 0 {
+            return Err(anyhow!(
+                "Unexpected end of cpio archive"
+            ));
+        }
+        offset += file_size;
+        skip_padding(&mut reader, &mut offset)?;
+    }
+    // データを持たないまま終わったハードリンクは空ファイルとして作成
+    for path in hardlinks.into_values().flatten() {
+        safe_path::prepare(dest, &path)?;
+        File::create(&path)?;
+    }
+    Ok(())
+}
+
+/// `data`を`paths`の最初のファイルに書き出し、残りのパス(ハードリンク)にはその複製を作成します。
+fn write_file<R: Read>(
+    data: &mut R,
+    dest: &Path,
+    paths: &[PathBuf],
+    mode: u32,
+) -> Result<()> {
+    let permissions = fs::Permissions::from_mode(mode & 0o7777);
+    let (first, rest) = paths.split_first().unwrap();
+    safe_path::prepare(dest, first)?;
+    io::copy(data, &mut File::create(first)?)?;
+    fs::set_permissions(first, permissions.clone())?;
+    for path in rest {
+        safe_path::prepare(dest, path)?;
+        fs::copy(first, path)?;
+        fs::set_permissions(path, permissions.clone())?;
+    }
+    Ok(())
+}
+
+/// 4バイト境界までのパディングを読み飛ばします。
+fn skip_padding<R: Read>(
+    reader: &mut R,
+    offset: &mut usize,
+) -> Result<()> {
+    let padding = (4 - *offset % 4) % 4;
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf[..padding])?;
+    *offset += padding;
+    Ok(())
+}
+
+/// アーカイブ内のパスを`dest`以下のパスに変換します。
+/// `..`などで`dest`の外を指すパスは無視します。
+fn safe_join(dest: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = dest.to_path_buf();
+    let mut has_component = false;
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => {
+                path.push(part);
+                has_component = true;
+            }
+            Component::CurDir | Component::RootDir => continue,
+            _ => return None,
+        }
+    }
+    has_component.then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+
+    /// newc形式のエントリ (サイズは`size`、データは`data`)
+    fn entry(
+        name: &str,
+        mode: u32,
+        size: u32,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let name = format!("{}\0", name);
+        let mut fields = [0u32; 13];
+        fields[1] = mode;
+        fields[4] = 1;
+        fields[6] = size;
+        fields[11] = name.len() as u32;
+        let mut entry = b"070701".to_vec();
+        for field in fields {
+            entry.extend(format!("{:08x}", field).bytes());
+        }
+        entry.extend(name.bytes());
+        entry.resize(entry.len().next_multiple_of(4), 0);
+        entry.extend(data);
+        entry.resize(entry.len().next_multiple_of(4), 0);
+        entry
+    }
+
+    #[test]
+    fn test_unpack_through_symlink() -> Result<()> {
+        let dir = TempDir::new("cpio-symlink")?;
+        let outside = dir.path().join("outside");
+        fs::create_dir(&outside)?;
+        let target = outside.to_string_lossy();
+        let mut archive = entry(
+            "./usr",
+            S_IFLNK | 0o777,
+            target.len() as u32,
+            target.as_bytes(),
+        );
+        archive.extend(entry(
+            "./usr/x",
+            S_IFREG | 0o644,
+            2,
+            b"x\n",
+        ));
+        archive.extend(entry("TRAILER!!!", 0, 0, b""));
+
+        let dest = dir.path().join("dest");
+        assert!(unpack(archive.as_slice(), &dest).is_err());
+        assert!(!outside.join("x").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_truncated() -> Result<()> {
+        let dir = TempDir::new("cpio-truncated")?;
+        // 実際のデータより大きなサイズを主張するエントリ
+        let archive =
+            entry("./big", S_IFREG | 0o644, u32::MAX, b"data");
+        let error =
+            unpack(archive.as_slice(), dir.path()).unwrap_err();
+        assert!(error.to_string().contains("Unexpected end"));
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use crate::modules::pkg::relation::ExtraRelationData;
+use crate::modules::repo::types::apt;
+use crate::utils::compression;
+use anyhow::{Result, anyhow};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use ipak::modules::pkg::PackageData;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const POSTINST_SCRIPT: &str =
+    include_str!("scripts/deb-postinst.sh");
+const PRERM_SCRIPT: &str = include_str!("scripts/deb-prerm.sh");
+
+/// control.tarに含めるメンテナスクリプト
+const MAINTAINER_SCRIPTS: [&str; 4] =
+    ["preinst", "postinst", "prerm", "postrm"];
+/// control.tarに引き継ぐメンテナスクリプト以外のファイル
+const CONTROL_FILES: [&str; 7] = [
+    "conffiles",
+    "md5sums",
+    "shlibs",
+    "symbols",
+    "triggers",
+    "templates",
+    "config",
+];
+
+/// .debファイル(ar形式)を`dest`に展開し、controlファイルからPackageDataを生成します。
+///
+/// 展開後の構成:
+/// - `debian-binary`
+/// - `control/` (control.tar.* の中身)
+/// - `data/` (data.tar.* の中身)
+pub fn extract(
+    deb_path: &Path,
+    dest: &Path,
+) -> Result<PackageData> {
+    let mut archive = ar::Archive::new(File::open(deb_path)?);
+    let mut has_control = false;
+    let mut has_data = false;
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let name =
+            String::from_utf8_lossy(entry.header().identifier())
+                .trim_end_matches('/')
+                .to_string();
+        if name == "debian-binary" {
+            let mut format_version = String::new();
+            entry.read_to_string(&mut format_version)?;
+            if !format_version.trim().starts_with("2.") {
+                return Err(anyhow!(
+                    "Unsupported deb format version: {}",
+                    format_version.trim()
+                ));
+            }
+            fs::write(
+                dest.join("debian-binary"),
+                format_version,
+            )?;
+        } else if name.starts_with("control.tar") {
+            unpack_tar(&name, entry, &dest.join("control"))?;
+            has_control = true;
+        } else if name.starts_with("data.tar") {
+            unpack_tar(&name, entry, &dest.join("data"))?;
+            has_data = true;
+        }
+    }
+    if !has_control {
+        return Err(anyhow!("control.tar not found in package"));
+    }
+    if !has_data {
+        return Err(anyhow!("data.tar not found in package"));
+    }
+
+    let control_content =
+        fs::read_to_string(dest.join("control/control"))?;
+    let control_data =
+        apt::parse_control_file(&control_content)?;
+    apt::to_package_data(&control_data)
+}
+
+/// 圧縮されたtarを拡張子から判別して展開します。
+fn unpack_tar<R: Read>(
+    name: &str,
+    reader: R,
+    dest: &Path,
+) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    let decoder = compression::decoder(name, reader)?;
+    let mut archive = tar::Archive::new(decoder);
+    archive.set_preserve_permissions(true);
+    archive.unpack(dest)?;
+    Ok(())
+}
+
+/// 作業ディレクトリの内容から.debファイルを作成します。
+///
+/// `data/`がある場合(debから変換されたipak)はその中身をそのまま配置し、
+/// ない場合はパッケージ全体を`/opt/ipak/<name>/`に配置して、
+/// postinst/prermからipakのinstall.sh/remove.shを呼び出します。
+pub fn build(
+    stage_dir: &Path,
+    package_data: &PackageData,
+    extra: &ExtraRelationData,
+    output: &Path,
+) -> Result<()> {
+    let name = &package_data.about.package.name;
+    let version = package_data.about.package.version.to_string();
+    let data_dir = stage_dir.join("data");
+    let control_dir = stage_dir.join("control");
+    let mut control_members: Vec<(String, Vec<u8>, u32)> =
+        vec![];
+
+    let mut data_tar = tar::Builder::new(GzEncoder::new(
+        Vec::new(),
+        Compression::default(),
+    ));
+    data_tar.mode(tar::HeaderMode::Deterministic);
+    data_tar.follow_symlinks(false);
+    let installed_size = if data_dir.is_dir() {
+        data_tar.append_dir_all(".", &data_dir)?;
+        dir_size(&data_dir)?
+    } else {
+        let package_dir = format!("./opt/ipak/{}", name);
+        for dir in ["./", "./opt/", "./opt/ipak/"] {
+            append_dir_entry(&mut data_tar, dir)?;
+        }
+        data_tar.append_dir_all(&package_dir, stage_dir)?;
+        for (script_name, template) in [
+            ("postinst", POSTINST_SCRIPT),
+            ("prerm", PRERM_SCRIPT),
+        ] {
+            let script = template
+                .replace("@NAME@", name)
+                .replace("@VERSION@", &version);
+            control_members.push((
+                script_name.to_string(),
+                script.into_bytes(),
+                0o755,
+            ));
+        }
+        dir_size(stage_dir)?
+    };
+    let data_tar = data_tar.into_inner()?.finish()?;
+
+    // 元のdebに含まれていたメンテナスクリプト等を引き継ぐ
+    if control_dir.is_dir() && control_members.is_empty() {
+        for entry in fs::read_dir(&control_dir)? {
+            let path = entry?.path();
+            let file_name = path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            let is_script =
+                MAINTAINER_SCRIPTS.contains(&file_name.as_str());
+            if !path.is_file()
+                || !(is_script
+                    || CONTROL_FILES
+                        .contains(&file_name.as_str()))
+            {
+                continue;
+            }
+            let mode = if is_script { 0o755 } else { 0o644 };
+            control_members.push((
+                file_name,
+                fs::read(&path)?,
+                mode,
+            ));
+        }
+    }
+    let control = apt::to_control_file(
+        package_data,
+        extra,
+        Some(installed_size.div_ceil(1024)),
+    );
+    control_members.insert(
+        0,
+        ("control".to_string(), control.into_bytes(), 0o644),
+    );
+    let control_tar = build_control_tar(&control_members)?;
+
+    let mut deb = ar::Builder::new(File::create(output)?);
+    for (member_name, content) in [
+        ("debian-binary", b"2.0\n".to_vec()),
+        ("control.tar.gz", control_tar),
+        ("data.tar.gz", data_tar),
+    ] {
+        let mut header = ar::Header::new(
+            member_name.as_bytes().to_vec(),
+            content.len() as u64,
+        );
+        header.set_mode(0o100644);
+        header.set_mtime(now());
+        deb.append(&header, content.as_slice())?;
+    }
+    Ok(())
+}
+
+/// .debのファイル名(`<name>_<version>_<arch>.deb`)を返します。
+pub fn file_name(package_data: &PackageData) -> String {
+    let version = package_data.about.package.version.to_string();
+    // エポックはファイル名に含めない
+    let version = version
+        .split_once(':')
+        .map(|(_, v)| v.to_string())
+        .unwrap_or(version);
+    format!(
+        "{}_{}_{}.deb",
+        package_data.about.package.name,
+        version,
+        apt::debian_architecture(&package_data.architecture)
+    )
+}
+
+fn build_control_tar(
+    members: &[(String, Vec<u8>, u32)],
+) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        Vec::new(),
+        Compression::default(),
+    ));
+    append_dir_entry(&mut builder, "./")?;
+    for (name, content, mode) in members {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(*mode);
+        header.set_mtime(now());
+        header.set_cksum();
+        builder.append_data(
+            &mut header,
+            format!("./{}", name),
+            content.as_slice(),
+        )?;
+    }
+    Ok(builder.into_inner()?.finish()?)
+}
+
+fn append_dir_entry<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_size(0);
+    header.set_mode(0o755);
+    header.set_mtime(now());
+    header.set_cksum();
+    builder.append_data(&mut header, path, std::io::empty())?;
+    Ok(())
+}
+
+/// ディレクトリ以下のファイルサイズの合計(バイト)
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = fs::symlink_metadata(entry.path())?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
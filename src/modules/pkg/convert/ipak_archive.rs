@@ -0,0 +1,202 @@
+use super::safe_path;
+use ipak::modules::pkg::PackageData;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{PermissionsExt, symlink};
+use std::path::{Component, Path, PathBuf};
+use zip::write::{SimpleFileOptions, ZipWriter};
+use zip::{CompressionMethod, ZipArchive};
+
+const INSTALL_SCRIPT: &str = include_str!("scripts/install.sh");
+const REMOVE_SCRIPT: &str = include_str!("scripts/remove.sh");
+const PURGE_SCRIPT: &str = include_str!("scripts/purge.sh");
+/// Debianのメンテナスクリプト(preinst/postinst/prerm/postrm)に
+/// `configure`や`upgrade`などの引数を渡すための互換レイヤー
+const DEB_COMPAT_SCRIPT: &str =
+    include_str!("scripts/deb-compat.sh");
+
+/// `ipak/project.yaml`とインストール用スクリプトを作業ディレクトリに書き出します。
+pub fn write_metadata(
+    stage_dir: &Path,
+    package_data: &PackageData,
+) -> Result<(), io::Error> {
+    let ipak_dir = stage_dir.join("ipak");
+    let scripts_dir = ipak_dir.join("scripts");
+    fs::create_dir_all(&scripts_dir)?;
+    let project_yaml = serde_yaml::to_string(package_data)
+        .map_err(|e| -> io::Error { io::Error::other(e) })?;
+    fs::write(ipak_dir.join("project.yaml"), project_yaml)?;
+    for (name, script) in [
+        ("install.sh", INSTALL_SCRIPT),
+        ("remove.sh", REMOVE_SCRIPT),
+        ("purge.sh", PURGE_SCRIPT),
+        ("deb-compat.sh", DEB_COMPAT_SCRIPT),
+    ] {
+        let path = scripts_dir.join(name);
+        fs::write(&path, script)?;
+        fs::set_permissions(
+            &path,
+            fs::Permissions::from_mode(0o755),
+        )?;
+    }
+    Ok(())
+}
+
+/// 作業ディレクトリを`root_name/`以下に格納した.ipak(zip形式)を作成します。
+pub fn pack(
+    stage_dir: &Path,
+    root_name: &str,
+    output: &Path,
+) -> Result<(), io::Error> {
+    let mut writer = ZipWriter::new(File::create(output)?);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored);
+    writer
+        .add_directory(format!("{}/", root_name), options)
+        .map_err(io::Error::other)?;
+    add_dir(&mut writer, stage_dir, root_name, options)?;
+    writer.finish().map_err(io::Error::other)?;
+    Ok(())
+}
+
+fn add_dir(
+    writer: &mut ZipWriter<File>,
+    dir: &Path,
+    prefix: &str,
+    options: SimpleFileOptions,
+) -> Result<(), io::Error> {
+    let mut entries = fs::read_dir(dir)?
+        .collect::<Result<Vec<fs::DirEntry>, io::Error>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = format!(
+            "{}/{}",
+            prefix,
+            entry.file_name().to_string_lossy()
+        );
+        let metadata = fs::symlink_metadata(&path)?;
+        let options = options
+            .unix_permissions(metadata.permissions().mode());
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&path)?;
+            writer
+                .add_symlink(
+                    name,
+                    target.to_string_lossy(),
+                    options,
+                )
+                .map_err(io::Error::other)?;
+        } else if metadata.is_dir() {
+            writer
+                .add_directory(format!("{}/", name), options)
+                .map_err(io::Error::other)?;
+            add_dir(writer, &path, &name, options)?;
+        } else {
+            writer
+                .start_file(name, options)
+                .map_err(io::Error::other)?;
+            writer.write_all(&fs::read(&path)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// .ipakを`dest`に展開し、`ipak/project.yaml`からPackageDataを読み込みます。
+///
+/// アーカイブ先頭の`<name>-<version>/`ディレクトリは取り除かれます。
+pub fn extract(
+    input: &Path,
+    dest: &Path,
+) -> Result<PackageData, io::Error> {
+    let mut archive = ZipArchive::new(File::open(input)?)
+        .map_err(|e| -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, e)
+        })?;
+    for i in 0..archive.len() {
+        let mut file =
+            archive.by_index(i).map_err(|e| -> io::Error {
+                io::Error::new(io::ErrorKind::InvalidData, e)
+            })?;
+        let Some(name) = file.enclosed_name() else {
+            continue;
+        };
+        let relative: PathBuf = name
+            .components()
+            .skip(1)
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let path = dest.join(relative);
+        safe_path::prepare(dest, &path)?;
+        if file.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if file.is_symlink() {
+            if file.size() > safe_path::MAX_LINK_TARGET {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Symbolic link target of {} is too long",
+                        path.display()
+                    ),
+                ));
+            }
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            symlink(target, &path)?;
+        } else {
+            io::copy(&mut file, &mut File::create(&path)?)?;
+            if let Some(mode) = file.unix_mode() {
+                fs::set_permissions(
+                    &path,
+                    fs::Permissions::from_mode(mode & 0o7777),
+                )?;
+            }
+        }
+    }
+    let project_yaml =
+        fs::read_to_string(dest.join("ipak/project.yaml"))?;
+    serde_yaml::from_str(&project_yaml).map_err(
+        |e| -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, e)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+
+    #[test]
+    fn test_extract_through_symlink() -> Result<(), io::Error> {
+        let dir = TempDir::new("ipak-symlink")?;
+        let outside = dir.path().join("outside");
+        fs::create_dir(&outside)?;
+        let input = dir.path().join("evil.ipak");
+        let mut writer = ZipWriter::new(File::create(&input)?);
+        let options = SimpleFileOptions::default();
+        writer
+            .add_symlink(
+                "evil-1.0/x",
+                outside.to_string_lossy(),
+                options,
+            )
+            .map_err(io::Error::other)?;
+        writer
+            .start_file("evil-1.0/x/passwd", options)
+            .map_err(io::Error::other)?;
+        writer.write_all(b"root::0:0::/root:/bin/sh\n")?;
+        writer.finish().map_err(io::Error::other)?;
+
+        let dest = dir.path().join("dest");
+        let error = extract(&input, &dest).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(!outside.join("passwd").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,484 @@
+use crate::modules::pkg::relation::ExtraRelationData;
+use crate::modules::repo::types::apt;
+use anyhow::{Result, anyhow};
+use ipak::modules::pkg::{
+    AboutData, AuthorAboutData, Mode, PackageAboutData,
+    PackageData, PackageRange, PackageVersion, RelationData,
+};
+use ipak::modules::version::{Version, VersionRange};
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// `.INSTALL`などのフックをDebianのメンテナスクリプトとして呼び出すためのテンプレート
+const HOOKS_SCRIPT: &str =
+    include_str!("scripts/pkginfo-hooks.sh");
+
+/// Alpineのフックスクリプトと、同じ時点で呼ばれるArchの`.INSTALL`の関数名
+const ALPINE_HOOKS: [(&str, &str); 6] = [
+    (".pre-install", "pre_install"),
+    (".post-install", "post_install"),
+    (".pre-upgrade", "pre_upgrade"),
+    (".post-upgrade", "post_upgrade"),
+    (".pre-deinstall", "pre_remove"),
+    (".post-deinstall", "post_remove"),
+];
+
+/// Arch Linux/Alpine Linuxのパッケージに含まれる`.PKGINFO`
+///
+/// どちらも`key = value`形式で、同じキーが複数回現れることがあります。
+pub struct PkgInfo {
+    fields: Vec<(String, String)>,
+}
+
+impl PkgInfo {
+    pub fn parse(content: &str) -> Self {
+        let fields = content
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| {
+                (
+                    key.trim().to_string(),
+                    value.trim().to_string(),
+                )
+            })
+            .collect();
+        Self { fields }
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn get_all(&self, key: &str) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// PackageDataに変換します。
+    ///
+    /// Alpineでは`depend`の先頭に`!`が付いたものが衝突を表し、
+    /// Archでは`conflict`として別に記載されます。
+    pub fn to_package_data(&self) -> Result<PackageData> {
+        let name = self
+            .get("pkgname")
+            .ok_or_else(|| {
+                anyhow!("'pkgname' not found in .PKGINFO")
+            })?
+            .to_string();
+        let version_str =
+            self.get("pkgver").ok_or_else(|| {
+                anyhow!("'pkgver' not found in .PKGINFO")
+            })?;
+        let version =
+            Version::from_str(version_str).map_err(|e| {
+                anyhow!(
+                    "Failed to parse version '{}': {}",
+                    version_str,
+                    e
+                )
+            })?;
+        let description =
+            self.get("pkgdesc").unwrap_or_default().to_string();
+        let maintainer = self
+            .get("packager")
+            .or_else(|| self.get("maintainer"))
+            .unwrap_or_default();
+        let (author_name, author_email) =
+            apt::parse_maintainer(maintainer);
+        let architecture = match self.get("arch") {
+            Some("any") | Some("noarch") => {
+                vec!["all".to_string()]
+            }
+            Some(arch) => vec![arch.to_string()],
+            None => vec![],
+        };
+
+        let mut depend = Vec::new();
+        let mut conflicts = Vec::new();
+        for dep in self.get_all("depend") {
+            match dep.strip_prefix('!') {
+                Some(conflict) => {
+                    conflicts.push(parse_dependency(conflict)?)
+                }
+                None => {
+                    depend.push(vec![parse_dependency(dep)?])
+                }
+            }
+        }
+        for conflict in self.get_all("conflict") {
+            conflicts.push(parse_dependency(conflict)?);
+        }
+        // optdependは"name: 説明"の形式
+        let suggests = self
+            .get_all("optdepend")
+            .into_iter()
+            .map(|opt| {
+                let name =
+                    opt.split_once(':').map_or(opt, |(n, _)| n);
+                parse_dependency(name.trim())
+                    .map(|range| vec![range])
+            })
+            .collect::<Result<Vec<Vec<PackageRange>>>>()?;
+        let virtuals = self
+            .get_all("provides")
+            .into_iter()
+            .map(parse_provide)
+            .collect::<Result<Vec<PackageVersion>>>()?;
+
+        Ok(PackageData {
+            about: AboutData {
+                author: AuthorAboutData {
+                    name: author_name,
+                    email: author_email,
+                },
+                package: PackageAboutData {
+                    name,
+                    version,
+                    description,
+                },
+            },
+            architecture,
+            mode: Mode::Global,
+            relation: RelationData {
+                depend,
+                suggests,
+                conflicts,
+                virtuals,
+                ..RelationData::default()
+            },
+        })
+    }
+
+    /// `control/`に展開された`.INSTALL`(Arch)や`.pre-install`など(Alpine)のフックを、
+    /// deb-compat.shやdpkgが実行するpreinst/postinst/prerm/postrmとして書き出します。
+    ///
+    /// `--to deb`でも使えるよう、フックの内容はスクリプトに埋め込みます。
+    /// 対応するものがないAlpineのトリガー(`.trigger`)は変換できないため、エラーにします。
+    pub fn write_maintainer_scripts(
+        &self,
+        dest: &Path,
+    ) -> Result<()> {
+        let control_dir = dest.join("control");
+        if control_dir.join(".trigger").exists() {
+            return Err(anyhow!(
+                "Alpine triggers (.trigger) cannot be converted"
+            ));
+        }
+        let (shell, hooks) = match fs::read_to_string(
+            control_dir.join(".INSTALL"),
+        ) {
+            // pacmanは.INSTALLをbashで読み込み、pre_installなどの関数を呼び出す
+            Ok(install) => ("/bin/bash", install),
+            Err(_) => {
+                let mut hooks = String::new();
+                for (file, function) in ALPINE_HOOKS {
+                    let Ok(script) = fs::read_to_string(
+                        control_dir.join(file),
+                    ) else {
+                        continue;
+                    };
+                    // 各フックは別のプロセスとして実行されるため、サブシェルの関数にする
+                    hooks.push_str(&format!(
+                        "{}() (\n{}\n)\n",
+                        function,
+                        script.trim_end()
+                    ));
+                }
+                ("/bin/sh", hooks)
+            }
+        };
+        if hooks.trim().is_empty() {
+            return Ok(());
+        }
+        let version: String = self
+            .get("pkgver")
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| {
+                c.is_ascii_alphanumeric()
+                    || ".:+~_-".contains(*c)
+            })
+            .collect();
+        let script = HOOKS_SCRIPT
+            .replace("@SHELL@", shell)
+            .replace("@VERSION@", &version)
+            .replace("@HOOKS@", hooks.trim_end());
+        for name in ["preinst", "postinst", "prerm", "postrm"] {
+            let path = control_dir.join(name);
+            fs::write(&path, &script)?;
+            fs::set_permissions(
+                &path,
+                fs::Permissions::from_mode(0o755),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// `replace`(Arch)/`replaces`(Alpine)をReplacesとして取り出します。
+    pub fn to_extra_relation_data(
+        &self,
+    ) -> Result<ExtraRelationData> {
+        let mut replaces = self.get_all("replaces");
+        replaces.extend(self.get_all("replace"));
+        Ok(ExtraRelationData {
+            replaces: replaces
+                .into_iter()
+                .map(parse_dependency)
+                .collect::<Result<Vec<PackageRange>>>()?,
+            ..ExtraRelationData::default()
+        })
+    }
+}
+
+/// 依存関係の文字列(例: "glibc>=2.38", "so:libc.musl-x86_64.so.1")を分割します。
+fn split_dependency(
+    input: &str,
+) -> (&str, Option<(&str, &str)>) {
+    match input.find(['<', '>', '=', '~']) {
+        Some(index) => {
+            let (name, constraint) = input.split_at(index);
+            let op_len = constraint
+                .find(|c: char| {
+                    !matches!(c, '<' | '>' | '=' | '~')
+                })
+                .unwrap_or(constraint.len());
+            let (op, version) = constraint.split_at(op_len);
+            (name.trim(), Some((op, version.trim())))
+        }
+        None => (input.trim(), None),
+    }
+}
+
+fn parse_dependency(input: &str) -> Result<PackageRange> {
+    let (name, constraint) = split_dependency(input);
+    let range = match constraint {
+        Some((op, version)) => {
+            let op = match op {
+                // Alpineの"~"はバージョンの前方一致なので、下限として扱う
+                "~" | "~=" => ">=",
+                "==" => "=",
+                op => op,
+            };
+            let range_str = format!("{} {}", op, version);
+            VersionRange::from_str(&range_str).map_err(|e| {
+                anyhow!(
+                    "Failed to parse version range '{}' for package '{}': {}",
+                    range_str,
+                    name,
+                    e
+                )
+            })?
+        }
+        None => VersionRange::default(),
+    };
+    Ok(PackageRange { name: name.to_string(), range })
+}
+
+fn parse_provide(input: &str) -> Result<PackageVersion> {
+    let (name, constraint) = split_dependency(input);
+    let version = match constraint {
+        Some((_, version)) => Version::from_str(version).map_err(|e| {
+            anyhow!(
+                "Failed to parse version '{}' for package '{}': {}",
+                version,
+                name,
+                e
+            )
+        })?,
+        None => Version::default(),
+    };
+    Ok(PackageVersion { name: name.to_string(), version })
+}
+
+/// tarアーカイブを展開し、`.PKGINFO`の内容を返します。
+///
+/// アーカイブ直下のドットファイル(`.PKGINFO`, `.MTREE`, `.INSTALL`,
+/// `.SIGN.*`, `.pre-install`など)は`control/`に、それ以外は`data/`に展開します。
+pub fn unpack<R: Read>(
+    mut archive: tar::Archive<R>,
+    dest: &Path,
+) -> Result<String> {
+    let data_dir = dest.join("data");
+    let control_dir = dest.join("control");
+    fs::create_dir_all(&data_dir)?;
+    fs::create_dir_all(&control_dir)?;
+    let mut pkginfo = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        let metadata_name = path
+            .to_str()
+            .filter(|p| p.starts_with('.') && !p.contains('/'))
+            .map(|p| p.to_string());
+        match metadata_name {
+            Some(name) if name == ".PKGINFO" => {
+                let mut content = String::new();
+                entry.read_to_string(&mut content)?;
+                fs::write(control_dir.join(&name), &content)?;
+                pkginfo = Some(content);
+            }
+            Some(name) => {
+                entry.unpack(control_dir.join(name))?;
+            }
+            None => {
+                entry.set_preserve_permissions(true);
+                entry.unpack_in(&data_dir)?;
+            }
+        }
+    }
+    pkginfo
+        .ok_or_else(|| anyhow!(".PKGINFO not found in package"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process::Command;
+
+    #[test]
+    fn test_parse_pkginfo() -> Result<()> {
+        let arch = PkgInfo::parse(
+            "# Generated by makepkg
+pkgname = hello
+pkgver = 1:2.0-1
+pkgdesc = Example hello package
+packager = Jane Doe <jane@example.com>
+arch = any
+depend = glibc>=2.38
+depend = sh
+optdepend = bash: for the bash completion
+provides = hello-bin=2.0
+conflict = hello-git
+replace = hello-old
+",
+        );
+        let package_data = arch.to_package_data()?;
+        assert_eq!(package_data.about.package.name, "hello");
+        assert_eq!(
+            package_data.about.package.version.to_string(),
+            "1:2.0-1"
+        );
+        assert_eq!(
+            package_data.about.author.email,
+            "jane@example.com"
+        );
+        assert_eq!(package_data.architecture, ["all"]);
+        let relation = &package_data.relation;
+        assert_eq!(relation.depend.len(), 2);
+        assert_eq!(relation.depend[0][0].name, "glibc");
+        assert_eq!(
+            relation.depend[0][0].range.to_string(),
+            ">= 2.38"
+        );
+        assert_eq!(relation.suggests[0][0].name, "bash");
+        assert_eq!(relation.conflicts[0].name, "hello-git");
+        assert_eq!(relation.virtuals[0].name, "hello-bin");
+        assert_eq!(
+            relation.virtuals[0].version.to_string(),
+            "2.0"
+        );
+        assert_eq!(
+            arch.to_extra_relation_data()?.replaces[0].name,
+            "hello-old"
+        );
+
+        // Alpineでは"!"の付いたdependが衝突を表す
+        let alpine = PkgInfo::parse(
+            "pkgname = hello
+pkgver = 2.38-r3
+depend = so:libc.musl-x86_64.so.1
+depend = !hello-legacy
+depend = busybox~1.36
+",
+        )
+        .to_package_data()?;
+        assert_eq!(
+            alpine.about.package.version.to_string(),
+            "2.38-r3"
+        );
+        let depend: Vec<&str> = alpine
+            .relation
+            .depend
+            .iter()
+            .map(|group| group[0].name.as_str())
+            .collect();
+        assert_eq!(
+            depend,
+            ["so:libc.musl-x86_64.so.1", "busybox"]
+        );
+        assert_eq!(
+            alpine.relation.depend[1][0].range.to_string(),
+            ">= 1.36"
+        );
+        assert_eq!(
+            alpine.relation.conflicts[0].name,
+            "hello-legacy"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_maintainer_scripts() -> Result<()> {
+        let dest = env::temp_dir().join(format!(
+            "ipm-test-pkginfo-hooks-{}",
+            std::process::id()
+        ));
+        let control_dir = dest.join("control");
+        fs::create_dir_all(&control_dir)?;
+        let log = dest.join("hooks.log");
+        // dpkgと同じく、スクリプト名は$DPKG_MAINTSCRIPT_NAMEで渡される
+        let run = |script: &str, args: &[&str]| -> Result<()> {
+            let status = Command::new(control_dir.join(script))
+                .args(args)
+                .env("DPKG_MAINTSCRIPT_NAME", script)
+                .env("IPM_HOOK_LOG", &log)
+                .status()?;
+            assert!(status.success(), "{} failed", script);
+            Ok(())
+        };
+        let result = (|| -> Result<String> {
+            fs::write(
+                control_dir.join(".post-install"),
+                "#!/bin/sh\necho \"post-install $1\" >> \"$IPM_HOOK_LOG\"\nexit 0\n",
+            )?;
+            fs::write(
+                control_dir.join(".pre-deinstall"),
+                "#!/bin/sh\necho \"pre-deinstall $1\" >> \"$IPM_HOOK_LOG\"\n",
+            )?;
+            let pkginfo = PkgInfo::parse("pkgver = 2.38-r3\n");
+            pkginfo.write_maintainer_scripts(&dest)?;
+            run("preinst", &["install"])?;
+            run("postinst", &["configure"])?;
+            run("prerm", &["remove"])?;
+            run("postrm", &["remove"])?;
+
+            // Alpineのトリガーは変換できない
+            fs::write(
+                control_dir.join(".trigger"),
+                "#!/bin/sh\n",
+            )?;
+            assert!(
+                pkginfo.write_maintainer_scripts(&dest).is_err()
+            );
+            Ok(fs::read_to_string(&log)?)
+        })();
+        fs::remove_dir_all(&dest)?;
+        // `exit 0`はサブシェル内で終わり、続くフックの呼び出しには影響しない
+        assert_eq!(
+            result?,
+            "post-install 2.38-r3\npre-deinstall 2.38-r3\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,555 @@
+use super::cpio;
+use crate::modules::repo::types::apt;
+use crate::utils::compression::Compression;
+use anyhow::{Result, anyhow};
+use ipak::modules::pkg::{
+    AboutData, AuthorAboutData, Mode, PackageAboutData,
+    PackageData, PackageRange, PackageVersion, RelationData,
+};
+use ipak::modules::version::{Version, VersionRange};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const LEAD_MAGIC: [u8; 4] = [0xed, 0xab, 0xee, 0xdb];
+const HEADER_MAGIC: [u8; 3] = [0x8e, 0xad, 0xe8];
+const LEAD_SIZE: usize = 96;
+
+// ヘッダのタグ
+const TAG_NAME: u32 = 1000;
+const TAG_VERSION: u32 = 1001;
+const TAG_RELEASE: u32 = 1002;
+const TAG_EPOCH: u32 = 1003;
+const TAG_SUMMARY: u32 = 1004;
+const TAG_DESCRIPTION: u32 = 1005;
+const TAG_VENDOR: u32 = 1011;
+const TAG_PACKAGER: u32 = 1015;
+const TAG_ARCH: u32 = 1022;
+const TAG_PROVIDENAME: u32 = 1047;
+const TAG_REQUIREFLAGS: u32 = 1048;
+const TAG_REQUIRENAME: u32 = 1049;
+const TAG_REQUIREVERSION: u32 = 1050;
+const TAG_CONFLICTFLAGS: u32 = 1053;
+const TAG_CONFLICTNAME: u32 = 1054;
+const TAG_CONFLICTVERSION: u32 = 1055;
+const TAG_PROVIDEFLAGS: u32 = 1112;
+const TAG_PROVIDEVERSION: u32 = 1113;
+const TAG_PAYLOADFORMAT: u32 = 1124;
+const TAG_PAYLOADCOMPRESSOR: u32 = 1125;
+const TAG_RECOMMENDNAME: u32 = 5046;
+const TAG_RECOMMENDVERSION: u32 = 5047;
+const TAG_RECOMMENDFLAGS: u32 = 5048;
+const TAG_SUGGESTNAME: u32 = 5049;
+const TAG_SUGGESTVERSION: u32 = 5050;
+const TAG_SUGGESTFLAGS: u32 = 5051;
+
+// 依存関係のフラグ
+const SENSE_LESS: u32 = 0x02;
+const SENSE_GREATER: u32 = 0x04;
+const SENSE_EQUAL: u32 = 0x08;
+
+/// ヘッダの値
+#[derive(Debug, Clone)]
+enum HeaderValue {
+    Int(Vec<u64>),
+    String(Vec<String>),
+    Binary,
+}
+
+/// RPMヘッダ(タグ番号と値の組)
+struct Header {
+    entries: HashMap<u32, HeaderValue>,
+}
+
+impl Header {
+    /// `data[offset..]`からヘッダを読み込み、ヘッダの終端位置とともに返します。
+    fn parse(
+        data: &[u8],
+        offset: usize,
+    ) -> Result<(Self, usize)> {
+        let intro = data
+            .get(offset..offset + 16)
+            .ok_or_else(|| anyhow!("Truncated RPM header"))?;
+        if intro[..3] != HEADER_MAGIC {
+            return Err(anyhow!("Invalid RPM header magic"));
+        }
+        let index_count = be_u32(&intro[8..12]) as usize;
+        let store_size = be_u32(&intro[12..16]) as usize;
+        let index_start = offset + 16;
+        let store_start = index_start + index_count * 16;
+        let end = store_start + store_size;
+        let store =
+            data.get(store_start..end).ok_or_else(|| {
+                anyhow!("Truncated RPM header store")
+            })?;
+
+        let mut entries = HashMap::new();
+        for i in 0..index_count {
+            let entry = &data[index_start + i * 16
+                ..index_start + i * 16 + 16];
+            let tag = be_u32(&entry[0..4]);
+            let kind = be_u32(&entry[4..8]);
+            let value_offset = be_u32(&entry[8..12]) as usize;
+            let count = be_u32(&entry[12..16]) as usize;
+            let value = store
+                .get(value_offset..)
+                .ok_or_else(|| {
+                    anyhow!("Invalid offset for tag {}", tag)
+                })
+                .and_then(|bytes| {
+                    read_value(kind, count, bytes)
+                })?;
+            entries.insert(tag, value);
+        }
+        Ok((Self { entries }, end))
+    }
+
+    fn string(&self, tag: u32) -> Option<String> {
+        match self.entries.get(&tag) {
+            Some(HeaderValue::String(values)) => {
+                values.first().cloned()
+            }
+            _ => None,
+        }
+    }
+
+    fn strings(&self, tag: u32) -> Vec<String> {
+        match self.entries.get(&tag) {
+            Some(HeaderValue::String(values)) => values.clone(),
+            _ => vec![],
+        }
+    }
+
+    fn ints(&self, tag: u32) -> Vec<u64> {
+        match self.entries.get(&tag) {
+            Some(HeaderValue::Int(values)) => values.clone(),
+            _ => vec![],
+        }
+    }
+}
+
+fn read_value(
+    kind: u32,
+    count: usize,
+    bytes: &[u8],
+) -> Result<HeaderValue> {
+    let ints = |size: usize| -> Result<HeaderValue> {
+        let bytes =
+            bytes.get(..size * count).ok_or_else(|| {
+                anyhow!("Truncated RPM header value")
+            })?;
+        Ok(HeaderValue::Int(
+            bytes
+                .chunks(size)
+                .map(|chunk| {
+                    chunk.iter().fold(0u64, |acc, b| {
+                        (acc << 8) | *b as u64
+                    })
+                })
+                .collect(),
+        ))
+    };
+    match kind {
+        // CHAR, INT8
+        1 | 2 => ints(1),
+        // INT16
+        3 => ints(2),
+        // INT32
+        4 => ints(4),
+        // INT64
+        5 => ints(8),
+        // STRING, STRING_ARRAY, I18NSTRING
+        6 | 8 | 9 => {
+            let count = if kind == 6 { 1 } else { count };
+            let values = bytes
+                .split(|b| *b == 0)
+                .take(count)
+                .map(|s| String::from_utf8_lossy(s).to_string())
+                .collect();
+            Ok(HeaderValue::String(values))
+        }
+        _ => Ok(HeaderValue::Binary),
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// .rpmファイルを`dest`に展開し、ヘッダからPackageDataを生成します。
+///
+/// ペイロード(cpio)は`data/`以下に展開されます。
+pub fn extract(
+    rpm_path: &Path,
+    dest: &Path,
+) -> Result<PackageData> {
+    let data = fs::read(rpm_path)?;
+    if data.len() < LEAD_SIZE || data[..4] != LEAD_MAGIC {
+        return Err(anyhow!("Not an RPM package"));
+    }
+    // 署名ヘッダは8バイト境界までパディングされている
+    let (_, signature_end) = Header::parse(&data, LEAD_SIZE)?;
+    let header_start = signature_end.div_ceil(8) * 8;
+    let (header, payload_start) =
+        Header::parse(&data, header_start)?;
+
+    let payload_format = header
+        .string(TAG_PAYLOADFORMAT)
+        .unwrap_or("cpio".to_string());
+    if payload_format != "cpio" {
+        return Err(anyhow!(
+            "Unsupported RPM payload format: {}",
+            payload_format
+        ));
+    }
+    let compression = match header
+        .string(TAG_PAYLOADCOMPRESSOR)
+        .unwrap_or("gzip".to_string())
+        .as_str()
+    {
+        "gzip" => Compression::Gzip,
+        "xz" => Compression::Xz,
+        "zstd" => Compression::Zstd,
+        compressor => {
+            return Err(anyhow!(
+                "Unsupported RPM payload compressor: {}",
+                compressor
+            ));
+        }
+    };
+    let payload = compression.decoder(&data[payload_start..])?;
+    let data_dir = dest.join("data");
+    fs::create_dir_all(&data_dir)?;
+    cpio::unpack(payload, &data_dir)?;
+
+    to_package_data(&header)
+}
+
+/// RPMヘッダからPackageDataを生成します。
+fn to_package_data(header: &Header) -> Result<PackageData> {
+    let name = header.string(TAG_NAME).ok_or_else(|| {
+        anyhow!("'Name' tag not found in RPM header")
+    })?;
+    let version_str = full_version(
+        header.ints(TAG_EPOCH).first().copied(),
+        &header.string(TAG_VERSION).ok_or_else(|| {
+            anyhow!("'Version' tag not found in RPM header")
+        })?,
+        header.string(TAG_RELEASE).as_deref(),
+    );
+    let version =
+        Version::from_str(&version_str).map_err(|e| {
+            anyhow!(
+                "Failed to parse version '{}': {}",
+                version_str,
+                e
+            )
+        })?;
+    let summary = header.string(TAG_SUMMARY).unwrap_or_default();
+    let description = match header.string(TAG_DESCRIPTION) {
+        Some(long) if !long.trim().is_empty() => {
+            format!("{}\n{}", summary, long.trim_end())
+        }
+        _ => summary,
+    };
+    let maintainer = header
+        .string(TAG_PACKAGER)
+        .or_else(|| header.string(TAG_VENDOR))
+        .unwrap_or_default();
+    let (author_name, author_email) =
+        apt::parse_maintainer(&maintainer);
+    let architecture = match header.string(TAG_ARCH) {
+        Some(arch) if arch == "noarch" => {
+            vec!["all".to_string()]
+        }
+        Some(arch) => vec![arch],
+        None => vec![],
+    };
+
+    let requires = dependencies(
+        header,
+        TAG_REQUIRENAME,
+        TAG_REQUIREFLAGS,
+        TAG_REQUIREVERSION,
+    );
+    let recommends = dependencies(
+        header,
+        TAG_RECOMMENDNAME,
+        TAG_RECOMMENDFLAGS,
+        TAG_RECOMMENDVERSION,
+    );
+    let suggests = dependencies(
+        header,
+        TAG_SUGGESTNAME,
+        TAG_SUGGESTFLAGS,
+        TAG_SUGGESTVERSION,
+    );
+    let conflicts = dependencies(
+        header,
+        TAG_CONFLICTNAME,
+        TAG_CONFLICTFLAGS,
+        TAG_CONFLICTVERSION,
+    );
+    let provides = dependencies(
+        header,
+        TAG_PROVIDENAME,
+        TAG_PROVIDEFLAGS,
+        TAG_PROVIDEVERSION,
+    );
+
+    let virtuals = provides
+        .into_iter()
+        .flatten()
+        .filter(|dep| dep.name != name)
+        .map(|dep| -> Result<PackageVersion> {
+            let version = match dep.constraint {
+                Some((_, version)) => {
+                    Version::from_str(&version).map_err(|e| {
+                        anyhow!(
+                            "Failed to parse version '{}' for package '{}': {}",
+                            version,
+                            dep.name,
+                            e
+                        )
+                    })?
+                }
+                None => Version::default(),
+            };
+            Ok(PackageVersion { name: dep.name, version })
+        })
+        .collect::<Result<Vec<PackageVersion>>>()?;
+    let relation = RelationData {
+        depend: to_package_ranges(requires)?,
+        recommends: to_package_ranges(recommends)?,
+        suggests: to_package_ranges(suggests)?,
+        conflicts: to_package_ranges(conflicts)?
+            .into_iter()
+            .flatten()
+            .collect(),
+        virtuals,
+        ..RelationData::default()
+    };
+
+    Ok(PackageData {
+        about: AboutData {
+            author: AuthorAboutData {
+                name: author_name,
+                email: author_email,
+            },
+            package: PackageAboutData {
+                name,
+                version,
+                description,
+            },
+        },
+        architecture,
+        mode: Mode::Global,
+        relation,
+    })
+}
+
+/// "[epoch:]version[-release]"形式のバージョン文字列を組み立てます。
+fn full_version(
+    epoch: Option<u64>,
+    version: &str,
+    release: Option<&str>,
+) -> String {
+    let mut full = String::new();
+    if let Some(epoch) = epoch {
+        full.push_str(&format!("{}:", epoch));
+    }
+    full.push_str(version);
+    if let Some(release) = release {
+        full.push_str(&format!("-{}", release));
+    }
+    full
+}
+
+/// RPMの依存関係1件
+struct Dependency {
+    name: String,
+    /// (演算子, バージョン)
+    constraint: Option<(&'static str, String)>,
+}
+
+/// 名前・フラグ・バージョンの3つのタグから依存関係を組み立てます。
+///
+/// 返り値の各要素は選択肢のグループで、`(a or b)`形式のリッチ依存は
+/// 複数の選択肢を持つグループになります。
+fn dependencies(
+    header: &Header,
+    name_tag: u32,
+    flags_tag: u32,
+    version_tag: u32,
+) -> Vec<Vec<Dependency>> {
+    let names = header.strings(name_tag);
+    let flags = header.ints(flags_tag);
+    let versions = header.strings(version_tag);
+    let mut result = Vec::new();
+    for (i, name) in names.into_iter().enumerate() {
+        // rpmlib()やファイルパスへの依存はパッケージ名に対応しない
+        if name.starts_with("rpmlib(") || name.starts_with('/') {
+            continue;
+        }
+        if name.starts_with('(') {
+            if let Some(group) = parse_rich_dependency(&name) {
+                result.push(group);
+            }
+            continue;
+        }
+        let flag =
+            flags.get(i).copied().unwrap_or_default() as u32;
+        let version =
+            versions.get(i).cloned().unwrap_or_default();
+        let constraint = operator(flag)
+            .filter(|_| !version.is_empty())
+            .map(|op| (op, version));
+        result.push(vec![Dependency { name, constraint }]);
+    }
+    result
+}
+
+/// `(a or b >= 1.0)`形式のリッチ依存を選択肢のグループとして解釈します。
+/// `and`や`if`などを含むものは表現できないため無視します。
+fn parse_rich_dependency(
+    input: &str,
+) -> Option<Vec<Dependency>> {
+    let inner = input.strip_prefix('(')?.strip_suffix(')')?;
+    if inner.contains('(')
+        || [" and ", " if ", " unless ", " with ", " without "]
+            .iter()
+            .any(|keyword| inner.contains(keyword))
+    {
+        return None;
+    }
+    inner
+        .split(" or ")
+        .map(|alternative| {
+            let parts: Vec<&str> =
+                alternative.split_whitespace().collect();
+            match parts.as_slice() {
+                [name] => Some(Dependency {
+                    name: name.to_string(),
+                    constraint: None,
+                }),
+                [name, op, version] => Some(Dependency {
+                    name: name.to_string(),
+                    constraint: [">=", "<=", ">", "<", "="]
+                        .into_iter()
+                        .find(|known| known == op)
+                        .map(|op| (op, version.to_string())),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// 依存関係のフラグを比較演算子に変換します。
+fn operator(flags: u32) -> Option<&'static str> {
+    let less = flags & SENSE_LESS != 0;
+    let greater = flags & SENSE_GREATER != 0;
+    let equal = flags & SENSE_EQUAL != 0;
+    match (less, greater, equal) {
+        (true, false, true) => Some("<="),
+        (true, false, false) => Some("<"),
+        (false, true, true) => Some(">="),
+        (false, true, false) => Some(">"),
+        (false, false, true) => Some("="),
+        _ => None,
+    }
+}
+
+fn to_package_ranges(
+    groups: Vec<Vec<Dependency>>,
+) -> Result<Vec<Vec<PackageRange>>> {
+    groups
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .map(|dep| -> Result<PackageRange> {
+                    let range = match dep.constraint {
+                        Some((op, version)) => {
+                            let range_str = format!("{} {}", op, version);
+                            VersionRange::from_str(&range_str).map_err(
+                                |e| {
+                                    anyhow!(
+                                        "Failed to parse version range '{}' for package '{}': {}",
+                                        range_str,
+                                        dep.name,
+                                        e
+                                    )
+                                },
+                            )?
+                        }
+                        None => VersionRange::default(),
+                    };
+                    Ok(PackageRange { name: dep.name, range })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_extract_rpm() -> Result<()> {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tmp/hello-1.2-3.el9.x86_64.rpm");
+        let dest = env::temp_dir().join(format!(
+            "ipm-test-rpm-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dest)?;
+        let result = extract(&input, &dest);
+        let hello =
+            fs::read_to_string(dest.join("data/usr/bin/hello"));
+        fs::remove_dir_all(&dest)?;
+        let package_data = result?;
+        assert_eq!(hello?, "#!/bin/sh\necho hello\n");
+
+        let about = &package_data.about;
+        assert_eq!(about.package.name, "hello");
+        // "<version>-<release>"はそのまま保持する
+        assert_eq!(
+            about.package.version.to_string(),
+            "1.2-3.el9"
+        );
+        assert_eq!(
+            about.package.description,
+            "Example hello package\nPrints a friendly greeting."
+        );
+        assert_eq!(about.author.name, "Jane Doe");
+        assert_eq!(about.author.email, "jane@example.com");
+        assert_eq!(package_data.architecture, ["x86_64"]);
+
+        // rpmlib()とファイルパスへの依存は除かれる
+        let depend = &package_data.relation.depend;
+        assert_eq!(depend.len(), 2);
+        assert_eq!(depend[0][0].name, "glibc");
+        assert_eq!(depend[0][0].range.to_string(), ">= 2.34");
+        let names: Vec<&str> =
+            depend[1].iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["hello-data", "hello-data-legacy"]);
+        // 自身の名前のProvidesは仮想パッケージにしない
+        let virtuals = &package_data.relation.virtuals;
+        assert_eq!(virtuals.len(), 1);
+        assert_eq!(virtuals[0].name, "hello-bin");
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_version() {
+        assert_eq!(
+            full_version(None, "1.2", Some("3.el9")),
+            "1.2-3.el9"
+        );
+        assert_eq!(full_version(Some(1), "2.0", None), "1:2.0");
+    }
+}
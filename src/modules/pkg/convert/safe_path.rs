@@ -0,0 +1,62 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// シンボリックリンクの参照先として読み込む最大の長さ (PATH_MAX)
+pub const MAX_LINK_TARGET: u64 = 4096;
+
+/// アーカイブのエントリを`dest`以下の`path`に書き出す準備をします。
+///
+/// `dest`と親ディレクトリを順に作成し、途中に既存のシンボリックリンクがあればエラーを返します。
+/// 先に展開したシンボリックリンク(例: `usr -> /`)を経由して、
+/// `dest`の外に書き出されるのを防ぐためです。
+/// `path`自体がシンボリックリンクの場合は、参照先を書き換えないように削除します。
+pub fn prepare(
+    dest: &Path,
+    path: &Path,
+) -> Result<(), io::Error> {
+    let relative = path.strip_prefix(dest).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is outside {}",
+                path.display(),
+                dest.display()
+            ),
+        )
+    })?;
+    fs::create_dir_all(dest)?;
+    let mut current = dest.to_path_buf();
+    let mut components = relative.components().peekable();
+    while let Some(component) = components.next() {
+        current.push(component);
+        let metadata = fs::symlink_metadata(&current);
+        if components.peek().is_none() {
+            if metadata.is_ok_and(|m| m.file_type().is_symlink())
+            {
+                fs::remove_file(&current)?;
+            }
+            break;
+        }
+        match metadata {
+            Ok(metadata)
+                if metadata.file_type().is_symlink() =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Refusing to write {} through the symbolic link {}",
+                        path.display(),
+                        current.display()
+                    ),
+                ));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir(&current)?
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
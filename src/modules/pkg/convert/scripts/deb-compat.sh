@@ -0,0 +1,166 @@
+#!/bin/sh
+# Debianのメンテナスクリプトを実行するための互換レイヤー
+# install.sh / remove.sh / purge.sh から読み込まれる (カレントディレクトリはパッケージのルート)
+
+# インストール先のルートディレクトリ (パスは "/usr/bin/foo" の形式で後ろに連結する)
+# dpkg --root と同様に、IPAK_DEB_COMPAT_ROOT で別のディレクトリを指定できる
+DATA_DIR="${IPAK_DEB_COMPAT_ROOT:-}"
+
+# パッケージの状態 (バージョン、状態、conffilesのmd5) を保存するディレクトリ
+STATE_DIR="$DATA_DIR/var/lib/ipak/deb-compat/$IPAK_PROJECT_NAME"
+
+# dpkgがメンテナスクリプトに渡す環境変数
+export DPKG_MAINTSCRIPT_PACKAGE="$IPAK_PROJECT_NAME"
+export DPKG_MAINTSCRIPT_VERSION="$IPAK_PROJECT_VERSION"
+export DPKG_ROOT="$DATA_DIR"
+
+# 前回インストールされていたバージョンと状態 (installed / config-files)
+OLD_VERSION=""
+OLD_STATUS=""
+if [ -f "$STATE_DIR/version" ]; then
+    OLD_VERSION=$(cat "$STATE_DIR/version")
+fi
+if [ -f "$STATE_DIR/status" ]; then
+    OLD_STATUS=$(cat "$STATE_DIR/status")
+fi
+
+# control/ 以下のメンテナスクリプトを引数付きで実行する
+# 例: run_maintscript postinst configure 1.0-1
+run_maintscript() {
+    script="$1"
+    shift
+    if [ -f "control/$script" ]; then
+        chmod +x "control/$script"
+        DPKG_MAINTSCRIPT_NAME="$script" "./control/$script" "$@"
+    fi
+}
+
+# 前回インストールされたパッケージのメンテナスクリプトを実行する
+# 例: run_old_maintscript prerm upgrade 1.1-1
+run_old_maintscript() {
+    script="$1"
+    shift
+    if [ -f "$STATE_DIR/$script" ]; then
+        chmod +x "$STATE_DIR/$script"
+        DPKG_MAINTSCRIPT_NAME="$script" "$STATE_DIR/$script" "$@"
+    fi
+}
+
+# パスがconffilesに含まれているか (パスは "/etc/foo" の形式)
+is_conffile() {
+    [ -f "control/conffiles" ] && grep -qxF "$1" "control/conffiles"
+}
+
+# 前回インストール時のconffileのmd5
+old_conffile_md5() {
+    if [ -f "$STATE_DIR/conffiles" ]; then
+        awk -v path="$1" '$2 == path { print $1 }' "$STATE_DIR/conffiles"
+    fi
+}
+
+# conffilesを配置する
+# 利用者が変更していなければ新しいものに置き換え、変更されていれば
+# 既存のファイルを残して新しいものを "<path>.ipak-new" として配置する
+install_conffiles() {
+    [ -f "control/conffiles" ] || return 0
+    while read -r conffile; do
+        # "remove-on-upgrade" などのフラグ付きの行は対象外
+        case "$conffile" in
+            /*) ;;
+            *) continue ;;
+        esac
+        src="data$conffile"
+        target="$DATA_DIR$conffile"
+        [ -f "$src" ] || continue
+        mkdir -p "$(dirname "$target")"
+        if [ -f "$target" ]; then
+            current_md5=$(md5sum "$target" | cut -d ' ' -f 1)
+            new_md5=$(md5sum "$src" | cut -d ' ' -f 1)
+            if [ "$current_md5" = "$new_md5" ]; then
+                continue
+            fi
+            if [ "$current_md5" != "$(old_conffile_md5 "$conffile")" ]; then
+                echo "Keeping modified configuration file $conffile (new version installed as $conffile.ipak-new)"
+                cp -p "$src" "$target.ipak-new"
+                continue
+            fi
+        fi
+        cp -p "$src" "$target"
+    done < "control/conffiles"
+}
+
+# dataディレクトリのconffiles以外のファイルを配置する
+install_files() {
+    find data -mindepth 1 | while read -r file; do
+        rel_path="${file#data}"
+        target="$DATA_DIR$rel_path"
+        if [ -d "$file" ] && [ ! -L "$file" ]; then
+            mkdir -p "$target"
+        elif is_conffile "$rel_path"; then
+            continue
+        else
+            mkdir -p "$(dirname "$target")"
+            rm -f "$target"
+            cp -P -p "$file" "$target"
+        fi
+    done
+}
+
+# 旧バージョンにあり、新しいバージョンにはないファイルを削除する
+remove_obsolete_files() {
+    [ -f "$STATE_DIR/files" ] || return 0
+    while read -r rel_path; do
+        if [ ! -e "data$rel_path" ] && [ ! -L "data$rel_path" ]; then
+            rm -f "$DATA_DIR$rel_path"
+        fi
+    done < "$STATE_DIR/files"
+}
+
+# dataディレクトリのファイルを削除する (keep-conffiles を指定するとconffilesを残す)
+remove_files() {
+    find data -mindepth 1 ! -type d | while read -r file; do
+        rel_path="${file#data}"
+        if is_conffile "$rel_path"; then
+            if [ "$1" = "keep-conffiles" ]; then
+                continue
+            fi
+            rm -f "$DATA_DIR$rel_path.ipak-new"
+        fi
+        rm -f "$DATA_DIR$rel_path"
+    done
+    # 空になったディレクトリを削除
+    find data -mindepth 1 -type d | sort -r | while read -r dir; do
+        rmdir "$DATA_DIR${dir#data}" 2>/dev/null || true
+    done
+}
+
+# 状態を保存する (installed / config-files)
+save_state() {
+    mkdir -p "$STATE_DIR"
+    echo "$IPAK_PROJECT_VERSION" > "$STATE_DIR/version"
+    echo "$1" > "$STATE_DIR/status"
+    : > "$STATE_DIR/conffiles"
+    if [ -f "control/conffiles" ]; then
+        while read -r conffile; do
+            case "$conffile" in
+                /*) ;;
+                *) continue ;;
+            esac
+            if [ -f "data$conffile" ]; then
+                echo "$(md5sum "data$conffile" | cut -d ' ' -f 1) $conffile" >> "$STATE_DIR/conffiles"
+            fi
+        done < "control/conffiles"
+    fi
+    find data -mindepth 1 ! -type d | sed 's|^data||' > "$STATE_DIR/files"
+    # ipm pkg verify で使うチェックサムの一覧
+    if [ -f "ipak/manifest.yaml" ]; then
+        cp "ipak/manifest.yaml" "$STATE_DIR/manifest.yaml"
+    fi
+    # アップグレード時に旧バージョンとして実行するため保存しておく
+    for script in prerm postrm; do
+        rm -f "$STATE_DIR/$script"
+        if [ -f "control/$script" ]; then
+            cp "control/$script" "$STATE_DIR/$script"
+        fi
+    done
+}
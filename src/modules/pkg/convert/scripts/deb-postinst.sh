@@ -0,0 +1,14 @@
+#!/bin/sh
+set -e
+
+# ipm pkg convert --to deb により生成されたスクリプト
+# /opt/ipak 以下に展開されたipakパッケージのinstall.shを実行する
+IPAK_PACKAGE_DIR="/opt/ipak/@NAME@"
+export IPAK_PROJECT_NAME="@NAME@"
+export IPAK_PROJECT_VERSION="@VERSION@"
+export IPAK_INSTALL_MODE="global"
+
+if [ "$1" = "configure" ] && [ -f "$IPAK_PACKAGE_DIR/ipak/scripts/install.sh" ]; then
+    cd "$IPAK_PACKAGE_DIR"
+    sh ./ipak/scripts/install.sh
+fi
@@ -0,0 +1,18 @@
+#!/bin/sh
+set -e
+
+# ipm pkg convert --to deb により生成されたスクリプト
+# /opt/ipak 以下に展開されたipakパッケージのremove.shを実行する
+IPAK_PACKAGE_DIR="/opt/ipak/@NAME@"
+export IPAK_PROJECT_NAME="@NAME@"
+export IPAK_PROJECT_VERSION="@VERSION@"
+export IPAK_REMOVE_MODE="global"
+
+case "$1" in
+    remove|upgrade|deconfigure)
+        if [ -f "$IPAK_PACKAGE_DIR/ipak/scripts/remove.sh" ]; then
+            cd "$IPAK_PACKAGE_DIR"
+            sh ./ipak/scripts/remove.sh
+        fi
+        ;;
+esac
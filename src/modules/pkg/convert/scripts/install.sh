@@ -0,0 +1,42 @@
+#!/bin/sh
+set -e
+
+# グローバルモード以外は実行しない
+if [ "$IPAK_INSTALL_MODE" != "global" ]; then
+    echo "Error: Only global installation mode is supported"
+    exit 1
+fi
+
+. ./ipak/scripts/deb-compat.sh
+
+# preinst: 新規インストール / 設定ファイルのみ残っている場合 / アップグレード
+if [ "$OLD_STATUS" = "installed" ]; then
+    run_old_maintscript prerm upgrade "$IPAK_PROJECT_VERSION"
+    run_maintscript preinst upgrade "$OLD_VERSION"
+elif [ -n "$OLD_VERSION" ]; then
+    run_maintscript preinst install "$OLD_VERSION"
+else
+    run_maintscript preinst install
+fi
+
+# dataディレクトリから必要なファイルをコピー
+install_files
+
+if [ "$OLD_STATUS" = "installed" ]; then
+    remove_obsolete_files
+    run_old_maintscript postrm upgrade "$IPAK_PROJECT_VERSION"
+fi
+
+# 変更された設定ファイルは上書きしない
+install_conffiles
+
+# postinst: 前回のバージョンがあれば引数に渡す
+if [ -n "$OLD_VERSION" ]; then
+    run_maintscript postinst configure "$OLD_VERSION"
+else
+    run_maintscript postinst configure
+fi
+
+save_state installed
+
+echo "Installation of $IPAK_PROJECT_NAME $IPAK_PROJECT_VERSION completed successfully."
@@ -0,0 +1,39 @@
+#!@SHELL@
+
+# ipm pkg convert により生成されたスクリプト
+# Arch Linuxの.INSTALLやAlpine Linuxの.pre-installなどのフックを、
+# dpkg(またはdeb-compat.sh)がメンテナスクリプトに渡す引数に合わせて呼び出す
+# preinst/postinst/prerm/postrmは同じ内容で、$DPKG_MAINTSCRIPT_NAMEで区別する
+NEW_VERSION="@VERSION@"
+
+@HOOKS@
+
+# フックが定義されていれば実行する
+call_hook() {
+    if command -v "$1" >/dev/null 2>&1; then
+        "$@"
+    fi
+}
+
+case "$DPKG_MAINTSCRIPT_NAME $1" in
+    "preinst install")
+        call_hook pre_install "$NEW_VERSION"
+        ;;
+    "preinst upgrade")
+        call_hook pre_upgrade "$NEW_VERSION" "$2"
+        ;;
+    "postinst configure")
+        # 前回のバージョンが渡されればアップグレード
+        if [ -n "$2" ]; then
+            call_hook post_upgrade "$NEW_VERSION" "$2"
+        else
+            call_hook post_install "$NEW_VERSION"
+        fi
+        ;;
+    "prerm remove")
+        call_hook pre_remove "$NEW_VERSION"
+        ;;
+    "postrm remove")
+        call_hook post_remove "$NEW_VERSION"
+        ;;
+esac
@@ -0,0 +1,26 @@
+#!/bin/sh
+set -e
+
+# グローバルモード以外は実行しない
+if [ "$IPAK_PURGE_MODE" != "global" ]; then
+    echo "Error: Only global purge mode is supported"
+    exit 1
+fi
+
+. ./ipak/scripts/deb-compat.sh
+
+# まだ削除されていなければ、先にremoveと同じ処理を行う
+if [ "$OLD_STATUS" != "config-files" ]; then
+    run_maintscript prerm remove
+    remove_files keep-conffiles
+    run_maintscript postrm remove
+fi
+
+# 設定ファイルも削除
+remove_files
+
+run_maintscript postrm purge
+
+rm -rf "$STATE_DIR"
+
+echo "Purge of $IPAK_PROJECT_NAME $IPAK_PROJECT_VERSION completed successfully."
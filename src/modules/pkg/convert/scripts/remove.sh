@@ -0,0 +1,22 @@
+#!/bin/sh
+set -e
+
+# グローバルモード以外は実行しない
+if [ "$IPAK_REMOVE_MODE" != "global" ]; then
+    echo "Error: Only global removal mode is supported"
+    exit 1
+fi
+
+. ./ipak/scripts/deb-compat.sh
+
+run_maintscript prerm remove
+
+# 設定ファイルは残したままファイルを削除
+remove_files keep-conffiles
+
+run_maintscript postrm remove
+
+# 設定ファイルのみ残っている状態として記録 (再インストール時に参照される)
+save_state config-files
+
+echo "Removal of $IPAK_PROJECT_NAME $IPAK_PROJECT_VERSION completed successfully."
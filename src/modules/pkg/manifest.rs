@@ -0,0 +1,149 @@
+use crate::utils::hash;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// パッケージに含まれるファイルのチェックサム一覧(`ipak/manifest.yaml`)
+#[derive(Serialize, Deserialize, Default)]
+pub struct Manifest {
+    pub files: Vec<FileEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileEntry {
+    /// インストール先のルートからの相対パス (例: "usr/bin/hello")
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    /// debのmd5sumsに記載されていたチェックサム
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+}
+
+/// 検証で見つかった問題
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    Missing(String),
+    Modified(String),
+}
+
+impl Manifest {
+    /// `data_dir`以下の全てのファイルのSHA-256を計算して一覧を作成します。
+    ///
+    /// `md5sums`(debの`control/md5sums`の内容)が与えられた場合は、
+    /// 記載されたファイルのmd5を照合した上で一覧に含めます。
+    pub fn generate(
+        data_dir: &Path,
+        md5sums: Option<&str>,
+    ) -> Result<Self, io::Error> {
+        let md5sums =
+            md5sums.map(parse_md5sums).unwrap_or_default();
+        let mut files = Vec::new();
+        collect_files(data_dir, data_dir, &mut files)?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        for entry in &mut files {
+            let Some(expected) = md5sums.get(&entry.path) else {
+                continue;
+            };
+            let actual =
+                hash_file::<Md5>(&data_dir.join(&entry.path))?;
+            if &actual != expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("md5sum mismatch: {}", entry.path),
+                ));
+            }
+            entry.md5 = Some(actual);
+        }
+        Ok(Self { files })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, io::Error> {
+        let content = fs::read_to_string(path)?;
+        serde_yaml::from_str(&content).map_err(
+            |e| -> io::Error {
+                io::Error::new(io::ErrorKind::InvalidData, e)
+            },
+        )
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), io::Error> {
+        let content = serde_yaml::to_string(self)
+            .map_err(|e| -> io::Error { io::Error::other(e) })?;
+        fs::write(path, content)
+    }
+
+    /// `root`以下にインストールされたファイルを再計算し、
+    /// 変更または削除されたファイルを返します。
+    pub fn verify(
+        &self,
+        root: &Path,
+    ) -> Result<Vec<Problem>, io::Error> {
+        let mut problems = Vec::new();
+        for entry in &self.files {
+            let path = root.join(&entry.path);
+            if !path.is_file() {
+                problems
+                    .push(Problem::Missing(entry.path.clone()));
+            } else if hash_file::<Sha256>(&path)? != entry.sha256
+            {
+                problems
+                    .push(Problem::Modified(entry.path.clone()));
+            }
+        }
+        Ok(problems)
+    }
+}
+
+fn collect_files(
+    base: &Path,
+    dir: &Path,
+    files: &mut Vec<FileEntry>,
+) -> Result<(), io::Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            collect_files(base, &path, files)?;
+        } else if metadata.is_file() {
+            let relative = path
+                .strip_prefix(base)
+                .map_err(io::Error::other)?
+                .to_string_lossy()
+                .to_string();
+            files.push(FileEntry {
+                path: relative,
+                size: metadata.len(),
+                sha256: hash_file::<Sha256>(&path)?,
+                md5: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// md5sumsの内容("<md5>  <path>"の行)をパスとmd5の組に変換します。
+fn parse_md5sums(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .map(|(md5, path)| {
+            (
+                path.trim().trim_start_matches("./").to_string(),
+                md5.to_ascii_lowercase(),
+            )
+        })
+        .collect()
+}
+
+fn hash_file<D: Digest + io::Write>(
+    path: &Path,
+) -> Result<String, io::Error> {
+    let mut hasher = D::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hash::to_hex(&hasher.finalize()))
+}
@@ -0,0 +1,104 @@
+use ipak::modules::pkg::PackageRange;
+use ipak::utils::color::colorize::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// ipakのRelationDataでは表現できない依存関係
+/// (DebianのPre-Depends, Breaks, Replaces, Enhances)
+///
+/// Pre-DependsはRelationDataの`depend`にも含めるため、
+/// 依存関係を解決する側は通常の依存として扱えます。
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ExtraRelationData {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_depends: Vec<Vec<PackageRange>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breaks: Vec<PackageRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaces: Vec<PackageRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enhances: Vec<Vec<PackageRange>>,
+}
+
+impl ExtraRelationData {
+    pub fn is_empty(&self) -> bool {
+        self.pre_depends.is_empty()
+            && self.breaks.is_empty()
+            && self.replaces.is_empty()
+            && self.enhances.is_empty()
+    }
+
+    /// `ipak/relations.yaml`から読み込みます。ファイルがなければ空の値を返します。
+    pub fn from_file(path: &Path) -> Result<Self, io::Error> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        serde_yaml::from_str(&content).map_err(
+            |e| -> io::Error {
+                io::Error::new(io::ErrorKind::InvalidData, e)
+            },
+        )
+    }
+
+    /// `ipak/relations.yaml`に書き出します。空の場合は何もしません。
+    pub fn write(&self, path: &Path) -> Result<(), io::Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let content = serde_yaml::to_string(self)
+            .map_err(|e| -> io::Error { io::Error::other(e) })?;
+        fs::write(path, content)
+    }
+}
+
+fn format_range(package: &PackageRange) -> String {
+    let range = package.range.to_string();
+    if range.is_empty() || range == "*" {
+        package.name.clone()
+    } else {
+        format!("{} ({})", package.name, range)
+    }
+}
+
+fn format_groups(groups: &[Vec<PackageRange>]) -> String {
+    groups
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .map(format_range)
+                .collect::<Vec<String>>()
+                .join(" | ")
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn format_list(packages: &[PackageRange]) -> String {
+    packages
+        .iter()
+        .map(format_range)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl fmt::Display for ExtraRelationData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            ("Pre-Depends", format_groups(&self.pre_depends)),
+            ("Breaks", format_list(&self.breaks)),
+            ("Replaces", format_list(&self.replaces)),
+            ("Enhances", format_groups(&self.enhances)),
+        ];
+        for (key, value) in fields {
+            if !value.is_empty() {
+                writeln!(f, "{}: {}", key.bold(), value)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use super::manifest::{Manifest, Problem};
+use crate::modules::system::path;
+use cmd_arg::cmd_arg;
+use ipak::utils::color::colorize::*;
+use std::io;
+use std::path::Path;
+
+/// インストール済みのパッケージのファイルを再計算し、
+/// 変更・削除されたファイルを表示します。
+pub fn verify(
+    args: Vec<&cmd_arg::Option>,
+) -> Result<(), io::Error> {
+    let names: Vec<&str> = args
+        .iter()
+        .filter(|arg| {
+            arg.opt_type == cmd_arg::OptionType::Simple
+        })
+        .map(|arg| arg.opt_str.as_str())
+        .collect();
+    if names.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No package name specified",
+        ));
+    }
+    let mut problem_count = 0;
+    for name in names {
+        let manifest_path =
+            path::global::package_state_path(name)
+                .join("manifest.yaml");
+        problem_count += verify_package(
+            name,
+            &manifest_path,
+            Path::new("/"),
+        )?
+        .len();
+    }
+    if problem_count > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} files modified or missing",
+                problem_count
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// `manifest_path`の一覧と`root`以下のファイルを照合し、
+/// 見つかった問題を表示して返します。
+fn verify_package(
+    name: &str,
+    manifest_path: &Path,
+    root: &Path,
+) -> Result<Vec<Problem>, io::Error> {
+    if !manifest_path.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No integrity manifest found for {}", name),
+        ));
+    }
+    let manifest = Manifest::from_file(manifest_path)?;
+    let problems = manifest.verify(root)?;
+    for problem in &problems {
+        match problem {
+            Problem::Missing(file) => {
+                println!("{} /{}", "missing ".red().bold(), file)
+            }
+            Problem::Modified(file) => {
+                println!(
+                    "{} /{}",
+                    "modified".yellow().bold(),
+                    file
+                )
+            }
+        }
+    }
+    println!(
+        "{}: {} files checked, {} problems",
+        name.bold(),
+        manifest.files.len(),
+        problems.len()
+    );
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_verify_package() -> Result<(), io::Error> {
+        let work_dir = env::temp_dir().join(format!(
+            "ipm-test-verify-{}",
+            std::process::id()
+        ));
+        let root = work_dir.join("root");
+        let result = (|| -> Result<(), io::Error> {
+            fs::create_dir_all(root.join("usr/bin"))?;
+            fs::create_dir_all(root.join("etc"))?;
+            fs::write(root.join("usr/bin/hello"), "hello")?;
+            fs::write(root.join("usr/bin/hi"), "hi")?;
+            fs::write(root.join("etc/hello.conf"), "a=1")?;
+            let manifest_path = work_dir.join("manifest.yaml");
+            assert_eq!(
+                verify_package("hello", &manifest_path, &root)
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::NotFound
+            );
+            Manifest::generate(&root, None)?
+                .write(&manifest_path)?;
+            assert!(
+                verify_package("hello", &manifest_path, &root)?
+                    .is_empty()
+            );
+
+            // 同じ長さでも内容が変われば検出する
+            fs::write(root.join("usr/bin/hello"), "HELLO")?;
+            fs::remove_file(root.join("etc/hello.conf"))?;
+            assert_eq!(
+                verify_package("hello", &manifest_path, &root)?,
+                [
+                    Problem::Missing(
+                        "etc/hello.conf".to_string()
+                    ),
+                    Problem::Modified(
+                        "usr/bin/hello".to_string()
+                    ),
+                ]
+            );
+            Ok(())
+        })();
+        fs::remove_dir_all(&work_dir)?;
+        result
+    }
+}
@@ -1,13 +1,18 @@
 use super::messages;
+use super::pkg::relation::ExtraRelationData;
 use crate::utils::www::*;
 use chrono::{DateTime, Local};
 use cmd_arg::cmd_arg;
 use ipak::modules::pkg::{AuthorAboutData, PackageData};
 use ipak::utils::color::colorize::*;
+use types::apt::arch::MultiArch;
+use types::apt::signature::SignaturePolicy;
+use types::apt::version::{DebianVersion, Dependency};
+pub mod diagnostic;
 mod list;
 mod pkg;
 mod server;
-mod types;
+pub mod types;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use std::{fmt, io};
@@ -52,11 +57,30 @@ impl fmt::Display for RepoType {
         }
     }
 }
+/// リポジトリごとの設定
+#[derive(Clone, Default)]
+pub struct RepoOptions {
+    /// APTリポジトリの署名の検証方法
+    pub signature: SignaturePolicy,
+    /// APTリポジトリのアーカイブのルート(`dists/`と`pool/`を含むディレクトリ)。
+    /// 指定がなければインデックスのURLから求めます。
+    pub archive_root: Option<String>,
+    /// `dists/`を持たず、`Release`と`Packages`が同じディレクトリにある
+    /// フラットなAPTリポジトリかどうか
+    pub flat: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RepoData {
     pub author: AuthorAboutData, // pub に変更してテストでアクセス可能に
     pub last_modified: DateTime<Local>, // pub に変更してテストでアクセス可能に
     pub packages: Vec<PackageMetaData>, // pub に変更してテストでアクセス可能に
+    /// APTリポジトリの`Release`から得られた情報
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<types::apt::release::Release>,
+    /// 取得や解析で見つかった問題 (問題のあったパッケージは含まれません)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<diagnostic::Diagnostic>,
 }
 impl Default for RepoData {
     fn default() -> Self {
@@ -64,6 +88,8 @@ impl Default for RepoData {
             author: AuthorAboutData::default(),
             last_modified: Local::now(),
             packages: vec![],
+            release: None,
+            diagnostics: vec![],
         }
     }
 }
@@ -73,21 +99,171 @@ pub struct PackageMetaData {
     pub last_modified: DateTime<Local>, // pub に変更してテストでアクセス可能に
     pub info: PackageData, // pub に変更してテストでアクセス可能に
     pub url: String, // pub に変更してテストでアクセス可能に
+    /// Pre-Depends, Breaks, Replaces, Enhances
+    #[serde(default)]
+    pub extra_relation: ExtraRelationData,
+    /// APTリポジトリのパッケージのバージョン(dpkgの規則で比較できる)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debian_version: Option<DebianVersion>,
+    /// APTリポジトリのパッケージのPre-DependsとDepends
+    /// (バージョンの制約をdpkgの規則で判定できる)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub debian_depends: Vec<Vec<Dependency>>,
+    /// APTリポジトリのパッケージの`Multi-Arch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multi_arch: Option<MultiArch>,
+    /// サイズ、SHA256、Sectionなど、APTのインデックスに記載された付加情報
+    #[serde(default)]
+    pub details: PackageDetails,
+}
+
+/// APTのインデックスに記載された、パッケージファイルとパッケージの付加情報
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct PackageDetails {
+    /// パッケージファイルのサイズ (バイト)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// インストール後のサイズ (KiB)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_size: Option<u64>,
+    /// パッケージファイルのSHA256 (16進数)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    /// debtagsのタグ (例: "role::program")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl PackageDetails {
+    /// `section`に属するかどうかを返します。
+    /// "universe/utils"のようなコンポーネント付きのSectionは"utils"としても一致します。
+    pub fn in_section(&self, section: &str) -> bool {
+        self.section.as_deref().is_some_and(|s| {
+            s == section || s.rsplit('/').next() == Some(section)
+        })
+    }
+}
+
+impl fmt::Display for PackageDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            ("Section", self.section.clone()),
+            ("Priority", self.priority.clone()),
+            ("Homepage", self.homepage.clone()),
+            ("Size", self.size.map(|s| s.to_string())),
+            (
+                "Installed-Size",
+                self.installed_size
+                    .map(|s| format!("{} KiB", s)),
+            ),
+            ("SHA256", self.sha256.clone()),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                writeln!(f, "{}: {}", key.bold(), value)?;
+            }
+        }
+        if !self.tags.is_empty() {
+            writeln!(
+                f,
+                "{}: {}",
+                "Tag".bold(),
+                self.tags.join(", ")
+            )?;
+        }
+        Ok(())
+    }
 }
 impl RepoData {
     pub fn new(
         repo_type: RepoType,
         url: URL,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_options(
+            repo_type,
+            url,
+            &RepoOptions::default(),
+        )
+    }
+    /// リポジトリごとの設定(署名の検証に使う鍵束など)を指定して取得します。
+    pub fn with_options(
+        repo_type: RepoType,
+        url: URL,
+        options: &RepoOptions,
     ) -> Result<Self, std::io::Error> {
         match repo_type {
             RepoType::Ipm => types::ipm::fetch(url),
-            RepoType::Apt => types::apt::fetch(url),
+            RepoType::Apt => types::apt::fetch(url, options),
         }
     }
 }
+impl PackageMetaData {
+    /// `packages`のどのパッケージでも満たされないPre-Depends/Dependsの
+    /// 選択肢のグループを返します。
+    ///
+    /// バージョンの制約はdpkgの規則で判定し、Providesで提供される
+    /// パッケージ名は、制約がないか、バージョン付きで提供される場合に満たします。
+    pub fn unsatisfied_depends(
+        &self,
+        packages: &[PackageMetaData],
+    ) -> Vec<&[Dependency]> {
+        let satisfies = |dependency: &Dependency| {
+            packages.iter().any(|p| {
+                let provided = p
+                    .info
+                    .relation
+                    .virtuals
+                    .iter()
+                    .filter(|v| v.name == dependency.name)
+                    .any(|v| {
+                        let version = v.version.to_string();
+                        match &dependency.constraint {
+                            None => true,
+                            Some(constraint) => {
+                                version.parse().is_ok_and(|v| {
+                                    constraint.satisfied_by(&v)
+                                })
+                            }
+                        }
+                    });
+                provided
+                    || p.debian_version.as_ref().is_some_and(
+                        |v| {
+                            dependency.satisfied_by(
+                                &p.info.about.package.name,
+                                v,
+                            )
+                        },
+                    )
+            })
+        };
+        self.debian_depends
+            .iter()
+            .filter(|group| !group.iter().any(satisfies))
+            .map(Vec::as_slice)
+            .collect()
+    }
+}
+
 impl fmt::Display for PackageMetaData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{}", self.info)?;
+        write!(f, "{}", self.extra_relation)?;
+        if let Some(multi_arch) = self.multi_arch {
+            writeln!(
+                f,
+                "{}: {}",
+                "Multi-Arch".bold(),
+                multi_arch
+            )?;
+        }
+        write!(f, "{}", self.details)?;
         writeln!(
             f,
             "{}: {}",
@@ -107,6 +283,9 @@ impl fmt::Display for RepoData {
             "Last Modified".bold(),
             self.last_modified
         )?;
+        if let Some(release) = &self.release {
+            write!(f, "{}", release)?;
+        }
         for package in &self.packages {
             writeln!(f, "{}", package)?;
         }
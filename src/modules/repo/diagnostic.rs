@@ -0,0 +1,169 @@
+use ipak::utils::color::colorize::*;
+use serde::{Deserialize, Serialize};
+use std::{fmt, io};
+
+/// リポジトリの取得や解析で見つかった問題
+///
+/// 問題のあったパッケージは飛ばして処理を続けるため、
+/// 何が飛ばされたかを後からまとめて報告できるように記録します。
+#[derive(
+    Serialize, Deserialize, Clone, Debug, PartialEq, Eq,
+)]
+pub struct Diagnostic {
+    /// 問題のあったリポジトリ(インデックスのURL)
+    pub repo: String,
+    /// 問題のあった段落の番号 (1から数えます)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stanza: Option<usize>,
+    /// 問題のあった段落のパッケージ名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// 問題のあったフィールド
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+impl Diagnostic {
+    pub fn new(repo: &str, reason: impl fmt::Display) -> Self {
+        Self {
+            repo: repo.to_string(),
+            stanza: None,
+            package: None,
+            field: None,
+            reason: reason.to_string(),
+        }
+    }
+
+    /// 解析のエラーから作ります。
+    /// [`FieldError`]であれば、そのフィールド名も記録します。
+    pub fn from_error(
+        repo: &str,
+        error: &anyhow::Error,
+    ) -> Self {
+        match error.downcast_ref::<FieldError>() {
+            Some(e) => Self {
+                field: Some(e.field.clone()),
+                ..Self::new(repo, &e.reason)
+            },
+            None => Self::new(repo, error),
+        }
+    }
+
+    /// 問題のあった段落の番号とパッケージ名を付けます。
+    pub fn at(
+        mut self,
+        stanza: usize,
+        package: Option<&str>,
+    ) -> Self {
+        self.stanza = Some(stanza);
+        self.package = package.map(str::to_string);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.repo.cyan())?;
+        if let Some(stanza) = self.stanza {
+            write!(f, ": stanza {}", stanza)?;
+        }
+        if let Some(package) = &self.package {
+            write!(f, " ({})", package.bold())?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, ", field '{}'", field)?;
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+/// 段落のどのフィールドで解析に失敗したかを示すエラー
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, reason: impl fmt::Display) -> Self {
+        Self {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}': {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// 集めた問題を表示し、件数をまとめて表示します。
+///
+/// `strict`の場合、問題が1つでもあればエラーを返します。
+pub fn report(
+    diagnostics: &[Diagnostic],
+    strict: bool,
+) -> Result<(), io::Error> {
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+    for diagnostic in diagnostics {
+        eprintln!("{}: {}", "Warning".yellow(), diagnostic);
+    }
+    let mut repos: Vec<&str> =
+        diagnostics.iter().map(|d| d.repo.as_str()).collect();
+    repos.sort();
+    repos.dedup();
+    let summary = format!(
+        "{} problem(s) found in {} repository(ies)",
+        diagnostics.len(),
+        repos.len()
+    );
+    if strict {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            summary,
+        ));
+    }
+    eprintln!(
+        "{}: {} (use --strict to fail)",
+        "Warning".yellow(),
+        summary
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_diagnostics() {
+        let repo = "http://deb.debian.org/debian/dists/stable/main/binary-amd64";
+        let error = anyhow::Error::from(FieldError::new(
+            "Version",
+            "invalid version",
+        ));
+        let diagnostic = Diagnostic::from_error(repo, &error)
+            .at(3, Some("hello"));
+        assert_eq!(diagnostic.field.as_deref(), Some("Version"));
+        assert_eq!(diagnostic.reason, "invalid version");
+        assert_eq!(diagnostic.stanza, Some(3));
+
+        let diagnostic =
+            Diagnostic::from_error(repo, &anyhow!("broken"));
+        assert_eq!(diagnostic.field, None);
+        assert_eq!(diagnostic.reason, "broken");
+
+        assert!(report(&[], true).is_ok());
+        let diagnostics = [diagnostic];
+        assert!(report(&diagnostics, false).is_ok());
+        assert!(report(&diagnostics, true).is_err());
+    }
+}
@@ -1,20 +1,37 @@
 // src/modules/repo/list.rs
 use crate::modules::repo::PackageMetaData;
 use crate::modules::repo::RepoData;
+use crate::modules::repo::RepoOptions;
+use crate::modules::repo::diagnostic::{self, Diagnostic};
+use crate::modules::repo::types::apt;
+use crate::modules::repo::types::apt::contents::{
+    self, Provider,
+};
+use crate::modules::repo::types::apt::source_package::SourcePackage;
+use crate::modules::repo::types::apt::sources::{
+    self, SourceEntry,
+};
 use crate::modules::{repo::RepoType, system::path};
 use crate::utils::www::*;
 use ipak::utils::color::colorize::*;
 use std::fmt;
+use std::path::Path;
 use std::str::FromStr;
 
-pub fn packages() -> Result<Vec<PackageMetaData>, std::io::Error>
-{
+/// 全てのリポジトリのパッケージを返します。
+/// 取得できなかったリポジトリや解析できなかったパッケージは飛ばし、`diagnostics`に加えます。
+pub fn packages(
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<PackageMetaData>, std::io::Error> {
     // 同期的にリポジトリインデックスを取得
-    let repos = get_indexes()?;
+    let repos = get_indexes(diagnostics)?;
     let mut all_packages = Vec::new();
 
     // 各リポジトリを同期的に処理
     for repo_index in repos {
+        if repo_index.source {
+            continue;
+        }
         let url = repo_index.url.to_url().map_err(|e| {
             std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
@@ -23,41 +40,133 @@ pub fn packages() -> Result<Vec<PackageMetaData>, std::io::Error>
         })?;
 
         // 同期的にリポジトリデータを取得
-        match RepoData::new(repo_index.repo_type, url) {
+        match RepoData::with_options(
+            repo_index.repo_type,
+            url,
+            &repo_index.options,
+        ) {
             Ok(repo_data) => {
                 all_packages.extend(repo_data.packages);
+                diagnostics.extend(repo_data.diagnostics);
             }
-            Err(e) => {
-                eprintln!("Error fetching repository: {}", e);
-            }
+            Err(e) => diagnostics.push(Diagnostic::new(
+                &repo_index.url,
+                format!("Failed to fetch repository: {}", e),
+            )),
         }
     }
 
     Ok(all_packages)
 }
 
-fn get_indexes() -> Result<Vec<RepoIndex>, std::io::Error> {
+/// APTリポジトリの`Contents-<arch>`から、`pattern`に一致するパスを含むパッケージを探します。
+/// 同じパスとパッケージの組は1つにまとめます。
+pub fn provides(
+    pattern: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<Provider>, std::io::Error> {
+    let pattern = contents::pattern(pattern)?;
+    let mut result: Vec<Provider> = Vec::new();
+    for repo_index in get_indexes(diagnostics)? {
+        if !matches!(repo_index.repo_type, RepoType::Apt)
+            || repo_index.source
+        {
+            continue;
+        }
+        let url = repo_index.url.to_url().map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                e,
+            )
+        })?;
+        match apt::fetch_contents(
+            url,
+            &repo_index.options,
+            diagnostics,
+        ) {
+            Ok(index) => match contents::search(index, &pattern)
+            {
+                Ok(providers) => {
+                    for provider in providers {
+                        if !result.contains(&provider) {
+                            result.push(provider);
+                        }
+                    }
+                }
+                Err(e) => diagnostics.push(Diagnostic::new(
+                    &repo_index.url,
+                    format!("Failed to read contents: {}", e),
+                )),
+            },
+            Err(e) => diagnostics.push(Diagnostic::new(
+                &repo_index.url,
+                format!("Failed to fetch contents: {}", e),
+            )),
+        }
+    }
+    Ok(result)
+}
+
+/// `deb-src`で指定されたAPTリポジトリのソースパッケージを全て返します。
+pub fn source_packages(
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<SourcePackage>, std::io::Error> {
+    let mut result = Vec::new();
+    for repo_index in get_indexes(diagnostics)? {
+        if !repo_index.source {
+            continue;
+        }
+        let url = repo_index.url.to_url().map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                e,
+            )
+        })?;
+        match apt::fetch_sources(
+            url,
+            &repo_index.options,
+            diagnostics,
+        ) {
+            Ok(packages) => result.extend(packages),
+            Err(e) => diagnostics.push(Diagnostic::new(
+                &repo_index.url,
+                format!("Failed to fetch sources: {}", e),
+            )),
+        }
+    }
+    Ok(result)
+}
+
+/// 登録されたリポジトリを全て返します。
+/// 無視したエントリやオプションは`diagnostics`に加えます。
+fn get_indexes(
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<RepoIndex>, std::io::Error> {
     let local_repos = path::local::repo_list_path();
     let global_repos = path::global::repo_list_path(); // Note: This currently points to the same path as local_repos.
     let local_content = std::fs::read_to_string(&local_repos)
         .unwrap_or("".to_string());
     let global_content = std::fs::read_to_string(&global_repos)
         .unwrap_or("".to_string()); // Reads from the same path if local_repos and global_repos are identical
-    let mut repos = parse_repo(local_content)?;
-    repos.extend(parse_repo(global_content)?);
+    let mut repos = parse_repo(local_content, diagnostics)?;
+    repos.extend(parse_repo(global_content, diagnostics)?);
     Ok(repos)
 }
 pub fn list() -> Result<(), std::io::Error> {
-    let repos = get_indexes()?;
+    let mut diagnostics = Vec::new();
+    let repos = get_indexes(&mut diagnostics)?;
     println!("{}:{}\n", "Total Repos".bold(), repos.len());
     for repo in repos {
         println!("{}", repo);
     }
-    Ok(())
+    diagnostic::report(&diagnostics, false)
 }
 struct RepoIndex {
     repo_type: RepoType,
     url: String,
+    options: RepoOptions,
+    /// ソースパッケージのインデックス(`deb-src`)かどうか
+    source: bool,
 }
 impl fmt::Display for RepoIndex {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -66,11 +175,25 @@ impl fmt::Display for RepoIndex {
             "{}: {}",
             self.repo_type.to_string().bold(),
             self.url.cyan()
-        )
+        )?;
+        if self.source {
+            write!(f, " (source)")?;
+        }
+        if self.options.flat {
+            write!(f, " (flat)")?;
+        }
+        if self.options.signature.trusted {
+            write!(f, " (trusted)")?;
+        }
+        for keyring in &self.options.signature.keyrings {
+            write!(f, " (signed-by: {})", keyring.display())?;
+        }
+        Ok(())
     }
 }
 fn parse_repo(
     s: String,
+    diagnostics: &mut Vec<Diagnostic>,
 ) -> Result<Vec<RepoIndex>, std::io::Error> {
     let mut result: Vec<RepoIndex> = Vec::new();
     let lines: Vec<&str> = s.split('\n').collect();
@@ -79,19 +202,121 @@ fn parse_repo(
             continue;
         }
         if let Some((repo_type, url)) = line.split_once(':') {
+            let repo_type = RepoType::from_str(repo_type.trim())
+                .map_err(|e| -> std::io::Error {
+                    std::io::Error::other(e)
+                })?;
+            let url = url.trim();
+            if matches!(repo_type, RepoType::Apt)
+                && (url.starts_with("deb ")
+                    || url.starts_with("deb-src ")
+                    || url.starts_with('/'))
+            {
+                result.extend(parse_sources(url, diagnostics)?);
+                continue;
+            }
+            let (entry, url) =
+                sources::parse_options(url, diagnostics);
             result.push(RepoIndex {
-                repo_type: RepoType::from_str(repo_type.trim())
-                    .map_err(|e| -> std::io::Error {
-                        std::io::Error::other(e)
-                    })?,
-                url: url.trim().to_string(),
+                repo_type,
+                url: url.to_string(),
+                options: entry.options,
+                // ".../dists/<suite>/<component>/source/"はソースパッケージのインデックス
+                source: matches!(repo_type, RepoType::Apt)
+                    && url
+                        .trim_end_matches('/')
+                        .ends_with("/source"),
             });
         } else {
-            eprintln!(
-                "Warning: Malformed repository entry skipped: '{}'. Expected 'type:url' format.",
-                line
+            diagnostics.push(Diagnostic::new(
+                line.trim(),
+                "Malformed repository entry skipped. Expected 'type:url' format.",
+            ));
+        }
+    }
+    Ok(result)
+}
+
+/// sources.list形式の行(`deb http://... suite component...`)、
+/// または`.sources`/`.list`ファイルのパスを個々のインデックスに展開します。
+fn parse_sources(
+    s: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<RepoIndex>, std::io::Error> {
+    let entries = if s.starts_with('/') {
+        SourceEntry::from_file(Path::new(s), diagnostics)?
+    } else {
+        vec![SourceEntry::parse_line(s, diagnostics).map_err(
+            |e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    e.to_string(),
+                )
+            },
+        )?]
+    };
+    let mut result = Vec::new();
+    for entry in &entries {
+        let indexes = entry
+            .binary_indexes()
+            .into_iter()
+            .map(|index| (index, false))
+            .chain(
+                entry
+                    .source_indexes()
+                    .into_iter()
+                    .map(|index| (index, true)),
             );
+        for (index, source) in indexes {
+            result.push(RepoIndex {
+                repo_type: RepoType::Apt,
+                url: index.url,
+                // `archive-root=`や`flat=`で明示されていればそちらを優先する
+                options: RepoOptions {
+                    archive_root: entry
+                        .options
+                        .archive_root
+                        .clone()
+                        .or(Some(index.root)),
+                    flat: entry.options.flat || index.flat,
+                    ..entry.options.clone()
+                },
+                source,
+            });
         }
     }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sources_options() -> Result<(), std::io::Error>
+    {
+        let mut diagnostics = Vec::new();
+        let indexes = parse_sources(
+            "deb [archive-root=https://mirror.example/debian flat=yes] https://mirror.example/debian/stable ./",
+            &mut diagnostics,
+        )?;
+        assert_eq!(indexes.len(), 1);
+        assert_eq!(
+            indexes[0].options.archive_root.as_deref(),
+            Some("https://mirror.example/debian")
+        );
+        assert!(indexes[0].options.flat);
+
+        let indexes = parse_sources(
+            "deb https://mirror.example/debian stable main",
+            &mut diagnostics,
+        )?;
+        assert_eq!(
+            indexes[0].options.archive_root.as_deref(),
+            Some("https://mirror.example/debian")
+        );
+        assert!(!indexes[0].options.flat);
+        assert!(diagnostics.is_empty());
+        Ok(())
+    }
+}
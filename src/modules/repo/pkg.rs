@@ -1,5 +1,12 @@
+use super::PackageMetaData;
+use super::diagnostic;
 use super::list;
+use crate::utils::hash::HashingReader;
+use crate::utils::www::ToURL;
 use cmd_arg::cmd_arg;
+use ipak::utils::color::colorize::*;
+use std::fs;
+use std::io::Read;
 pub fn pkg(
     args: Vec<&cmd_arg::Option>,
 ) -> Result<(), std::io::Error> {
@@ -9,30 +16,210 @@ pub fn pkg(
         ));
     }
     let sub_cmd = args.first().unwrap().opt_str.to_string();
-    let sub_args = args[1..].to_vec();
+    // `--strict`: リポジトリの取得や解析で問題があれば失敗する
+    let strict =
+        args[1..].iter().any(|arg| arg.opt_str == "--strict");
+    let sub_args: Vec<&cmd_arg::Option> = args[1..]
+        .iter()
+        .filter(|arg| arg.opt_str != "--strict")
+        .copied()
+        .collect();
     match sub_cmd.as_str() {
         "search" => {
-            let pacakges = sub_args
+            let mut pacakges = Vec::new();
+            let mut section = None;
+            for arg in sub_args {
+                match arg.opt_str.as_str() {
+                    "--section" => {
+                        section = arg.opt_values.first().cloned()
+                    }
+                    _ => {
+                        if arg.opt_type
+                            == cmd_arg::OptionType::Simple
+                        {
+                            pacakges
+                                .push(arg.opt_str.to_string())
+                        }
+                    }
+                }
+            }
+            search_pkgs(pacakges, section, strict)
+        }
+        "source" => {
+            let packages = sub_args
+                .iter()
+                .map(|arg| -> String { arg.opt_str.to_string() })
+                .collect();
+            download_sources(packages, strict)
+        }
+        "provides" => {
+            let paths = sub_args
                 .iter()
                 .map(|arg| -> String { arg.opt_str.to_string() })
                 .collect();
-            search_pkgs(pacakges)
+            provides(paths, strict)
         }
         _ => Err(std::io::Error::from(
             std::io::ErrorKind::NotFound,
         )),
     }
 }
+/// パッケージの情報を表示します。
+///
+/// `section`が指定された場合はそのSectionのパッケージに絞り込み、
+/// パッケージ名の指定がなければ、そのSectionのパッケージを一覧表示します。
 fn search_pkgs(
     packages_name: Vec<String>,
+    section: Option<String>,
+    strict: bool,
 ) -> Result<(), std::io::Error> {
-    let packages = list::packages()?;
-    for name in packages_name {
-        if let Some(pkg) = packages
+    let mut diagnostics = Vec::new();
+    let all_packages = list::packages(&mut diagnostics)?;
+    diagnostic::report(&diagnostics, strict)?;
+    let packages: Vec<&PackageMetaData> = all_packages
+        .iter()
+        .filter(|p| {
+            section
+                .as_deref()
+                .is_none_or(|s| p.details.in_section(s))
+        })
+        .collect();
+    // 同じ名前のパッケージが複数ある場合は、最も新しいものを表示する
+    let newest = |name: &str| {
+        packages
             .iter()
-            .find(|p| p.info.about.package.name == name)
-        {
+            .filter(|p| p.info.about.package.name == name)
+            .reduce(|newest, p| {
+                if p.debian_version > newest.debian_version {
+                    p
+                } else {
+                    newest
+                }
+            })
+    };
+    if packages_name.is_empty() && section.is_some() {
+        let mut names: Vec<&str> = packages
+            .iter()
+            .map(|p| p.info.about.package.name.as_str())
+            .collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            if let Some(pkg) = newest(name) {
+                println!(
+                    "{} {}",
+                    name, pkg.info.about.package.version
+                );
+            }
+        }
+        return Ok(());
+    }
+    for name in packages_name {
+        if let Some(pkg) = newest(&name) {
             println!("{}", pkg.info);
+            print!("{}", pkg.extra_relation);
+            if let Some(multi_arch) = pkg.multi_arch {
+                println!("Multi-Arch: {}", multi_arch);
+            }
+            print!("{}", pkg.details);
+            for group in pkg.unsatisfied_depends(&all_packages) {
+                let group: Vec<String> = group
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect();
+                println!(
+                    "{}: {}",
+                    "Unsatisfied Depends".yellow(),
+                    group.join(" | ")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+/// パス(グロブも可)を含むパッケージを表示します。
+fn provides(
+    paths: Vec<String>,
+    strict: bool,
+) -> Result<(), std::io::Error> {
+    for path in paths {
+        let mut diagnostics = Vec::new();
+        let providers = list::provides(&path, &mut diagnostics)?;
+        diagnostic::report(&diagnostics, strict)?;
+        if providers.is_empty() {
+            eprintln!("No package provides '{}'", path);
+        }
+        for provider in providers {
+            println!("{}", provider);
+        }
+    }
+    Ok(())
+}
+/// ソースパッケージのファイル(.dsc、.orig.tar.*など)をカレントディレクトリにダウンロードします。
+///
+/// ソースパッケージ名で見つからない場合は、そのバイナリパッケージをビルドする
+/// ソースパッケージを探します。同じ名前のものが複数ある場合は、最も新しいものを使います。
+fn download_sources(
+    packages_name: Vec<String>,
+    strict: bool,
+) -> Result<(), std::io::Error> {
+    let mut diagnostics = Vec::new();
+    let sources = list::source_packages(&mut diagnostics)?;
+    diagnostic::report(&diagnostics, strict)?;
+    for name in packages_name {
+        let newest = |by_binary: bool| {
+            sources
+                .iter()
+                .filter(|s| {
+                    if by_binary {
+                        s.binaries.contains(&name)
+                    } else {
+                        s.name == name
+                    }
+                })
+                .max_by(|a, b| a.version.cmp(&b.version))
+        };
+        let Some(source) =
+            newest(false).or_else(|| newest(true))
+        else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Source package '{}' not found", name),
+            ));
+        };
+        print!("{}", source);
+        for file in &source.files {
+            let url = file.url.to_url().map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    e,
+                )
+            })?;
+            // 書き込み中のファイルは`.partial`とし、検証できた場合のみ置き換える
+            let partial = format!("{}.partial", file.name);
+            let result = (|| -> Result<(), std::io::Error> {
+                let body = url.open().map_err(|e| {
+                    std::io::Error::other(format!(
+                        "Failed to fetch {}: {}",
+                        url, e
+                    ))
+                })?;
+                // 記載されたサイズを超えて読み込まない
+                let mut reader =
+                    HashingReader::new(body.take(file.size + 1));
+                std::io::copy(
+                    &mut reader,
+                    &mut fs::File::create(&partial)?,
+                )?;
+                let (sha256, size) = reader.finish()?;
+                file.verify(&sha256, size)?;
+                fs::rename(&partial, &file.name)
+            })();
+            if result.is_err() {
+                let _ = fs::remove_file(&partial);
+            }
+            result?;
+            println!("Downloaded {}", file.name);
         }
     }
     Ok(())
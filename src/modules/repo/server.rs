@@ -1,6 +1,7 @@
 use super::super::messages;
 use cmd_arg::cmd_arg;
 mod build;
+mod http;
 mod init;
 mod metadata;
 mod project;
@@ -17,6 +18,7 @@ pub fn server(
         "project" | "proj" => project::project(sub_args)?,
         "build" => build::build()?,
         "metadata" | "info" => metadata::show_metadata()?,
+        "http" => http::serve(sub_args)?,
         _ => messages::unknown()?,
     }
     Ok(())
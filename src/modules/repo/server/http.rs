@@ -0,0 +1,620 @@
+use super::metadata;
+use chrono::{DateTime, Utc};
+use cmd_arg::cmd_arg;
+use ipak::utils::color::colorize::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// `--bind`の指定がない場合に待ち受けるアドレス
+const DEFAULT_BIND: &str = "127.0.0.1:8080";
+/// リクエストの1行の最大の長さ
+const MAX_LINE: u64 = 8 * 1024;
+/// リクエストのヘッダーの最大の数
+const MAX_HEADERS: usize = 100;
+/// 同時に応答する接続の最大の数
+const WORKERS: usize = 16;
+/// 応答を待つ接続の最大の数 (これを超えると新しい接続の受け付けを待たせます)
+const QUEUE: usize = 64;
+
+/// `build`で生成した`out/`ディレクトリ(repo.yamlとpackages/)をHTTPで配信します。
+///
+/// `--bind=<address>`または`--bind <address>`で待ち受けるアドレスを指定します。
+/// GETとHEADのみに対応し、Rangeによる部分的な取得とETagによる再検証ができます。
+pub fn serve(
+    args: Vec<&cmd_arg::Option>,
+) -> Result<(), io::Error> {
+    let mut bind = DEFAULT_BIND.to_string();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg.opt_str == "--bind" || arg.opt_str == "-b" {
+            bind = match arg.opt_values.first() {
+                Some(value) => value.to_owned(),
+                None => args
+                    .next()
+                    .map(|value| value.opt_str.to_owned())
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "--bind requires an address",
+                        )
+                    })?,
+            };
+        }
+    }
+    let root = metadata::get_dir()?.join("out");
+    if !root.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "{} not found. Run 'ipm repo serve build' first",
+                root.display()
+            ),
+        ));
+    }
+    let listener = TcpListener::bind(&bind)?;
+    println!(
+        "{} {} on http://{}",
+        "Serving".bold(),
+        root.display(),
+        listener.local_addr()?
+    );
+    let (sender, receiver) = mpsc::sync_channel(QUEUE);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..WORKERS {
+        let receiver = Arc::clone(&receiver);
+        let root = root.clone();
+        thread::spawn(move || work(&receiver, &root));
+    }
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        if sender.send(stream).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 受け付けた接続を順に取り出して応答します。
+fn work(receiver: &Mutex<Receiver<TcpStream>>, root: &Path) {
+    loop {
+        let stream = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        let Ok(stream) = stream else {
+            return;
+        };
+        if let Err(e) = handle(stream, root) {
+            eprintln!("Connection error: {}", e);
+        }
+    }
+}
+
+/// 1つの接続で1つのリクエストに応答します。
+fn handle(
+    stream: TcpStream,
+    root: &Path,
+) -> Result<(), io::Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            send_status(&mut stream, 400, vec![], false)?;
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    let status = respond(&mut stream, root, &request)?;
+    stream.flush()?;
+    println!("{} {} {}", request.method, request.target, status);
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    /// リクエストされたパス (クエリ文字列を含みます)
+    target: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// ヘッダーの値 (名前の大文字と小文字は区別しません)
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn read_line(
+    reader: &mut impl BufRead,
+) -> Result<String, io::Error> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Incomplete request line",
+        ));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_request(
+    reader: &mut impl BufRead,
+) -> Result<Request, io::Error> {
+    let line = read_line(reader)?;
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Malformed request line '{}'", line),
+        ));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported HTTP version '{}'", version),
+        ));
+    }
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Too many headers",
+            ));
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((
+                key.trim().to_string(),
+                value.trim().to_string(),
+            ));
+        }
+    }
+    Ok(Request {
+        method: method.to_string(),
+        target: target.to_string(),
+        headers,
+    })
+}
+
+/// リクエストに応答し、応答のステータスコードを返します。
+fn respond(
+    out: &mut impl Write,
+    root: &Path,
+    request: &Request,
+) -> Result<u16, io::Error> {
+    let head = request.method == "HEAD";
+    if request.method != "GET" && !head {
+        let allow = vec![("Allow", "GET, HEAD".to_string())];
+        return send_status(out, 405, allow, head);
+    }
+    let Some(path) =
+        resolve(root, &request.target).filter(|p| p.is_file())
+    else {
+        return send_status(out, 404, vec![], head);
+    };
+    let mut file = File::open(&path)?;
+    let metadata = file.metadata()?;
+    let len = metadata.len();
+    let modified: DateTime<Utc> = metadata.modified()?.into();
+    let etag = format!(
+        "\"{:x}-{:x}\"",
+        len,
+        modified.timestamp_micros()
+    );
+    let mut headers = vec![
+        ("Content-Type", content_type(&path).to_string()),
+        ("ETag", etag.clone()),
+        (
+            "Last-Modified",
+            modified
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string(),
+        ),
+        ("Accept-Ranges", "bytes".to_string()),
+    ];
+    if request
+        .header("If-None-Match")
+        .is_some_and(|value| etag_matches(value, &etag))
+    {
+        write_head(out, 304, &headers, None)?;
+        return Ok(304);
+    }
+    // If-Rangeが一致しなければ、ファイルが変わっているため全体を返す
+    let range = request
+        .header("Range")
+        .filter(|_| {
+            request
+                .header("If-Range")
+                .is_none_or(|value| value == etag)
+        })
+        .map_or(ByteRange::Full, |value| {
+            parse_range(value, len)
+        });
+    let (status, start, length) = match range {
+        ByteRange::Full => (200, 0, len),
+        ByteRange::Partial(start, end) => {
+            headers.push((
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, len),
+            ));
+            (206, start, end - start + 1)
+        }
+        ByteRange::Unsatisfiable => {
+            headers.push((
+                "Content-Range",
+                format!("bytes */{}", len),
+            ));
+            return send_status(out, 416, headers, head);
+        }
+    };
+    write_head(out, status, &headers, Some(length))?;
+    if !head {
+        file.seek(io::SeekFrom::Start(start))?;
+        io::copy(&mut file.take(length), out)?;
+    }
+    Ok(status)
+}
+
+/// リクエストされたパスを`root`以下のファイルのパスにします。
+/// `..`などで`root`の外を指す場合は`None`を返します。
+fn resolve(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next()?;
+    let path = percent_decode(path)?;
+    let mut resolved = root.to_path_buf();
+    for component in Path::new(&path).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::RootDir | Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+/// `%xx`で表されたバイトを戻します。不正な場合は`None`を返します。
+fn percent_decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut input = path.bytes();
+    while let Some(byte) = input.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = [input.next()?, input.next()?];
+        let hex = std::str::from_utf8(&hex).ok()?;
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+    }
+    String::from_utf8(bytes).ok().filter(|s| !s.contains('\0'))
+}
+
+/// 拡張子からContent-Typeを求めます。
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "yaml" | "yml" => "application/yaml",
+        "json" => "application/json",
+        "html" | "htm" => "text/html; charset=utf-8",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        "gz" | "tgz" => "application/gzip",
+        "xz" => "application/x-xz",
+        "zst" => "application/zstd",
+        "bz2" => "application/x-bzip2",
+        "tar" => "application/x-tar",
+        "zip" => "application/zip",
+        "deb" => "application/vnd.debian.binary-package",
+        "asc" | "gpg" | "sig" => "application/pgp-signature",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `If-None-Match`の値(`*`またはETagの一覧)が`etag`を含むか調べます。
+fn etag_matches(value: &str, etag: &str) -> bool {
+    value.split(',').map(str::trim).any(|tag| {
+        tag == "*" || tag.trim_start_matches("W/") == etag
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// 範囲の指定を無視して全体を返す
+    Full,
+    /// 先頭と末尾(末尾を含む)のバイト位置
+    Partial(u64, u64),
+    /// 範囲がファイルの外にある
+    Unsatisfiable,
+}
+
+/// `Range: bytes=<start>-<end>`を解析します。
+///
+/// 複数の範囲や解釈できない指定は無視し、全体を返します。
+fn parse_range(value: &str, len: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    if start.is_empty() {
+        // `bytes=-<n>`は末尾のnバイト
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(n) => ByteRange::Partial(
+                len.saturating_sub(n),
+                len - 1,
+            ),
+            Err(_) => ByteRange::Full,
+        };
+    }
+    let Ok(start) = start.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let end = if end.is_empty() {
+        u64::MAX
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return ByteRange::Full,
+        }
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start, end.min(len - 1))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        _ => "",
+    }
+}
+
+fn write_head(
+    out: &mut impl Write,
+    status: u16,
+    headers: &[(&str, String)],
+    content_length: Option<u64>,
+) -> Result<(), io::Error> {
+    write!(out, "HTTP/1.1 {} {}\r\n", status, reason(status))?;
+    for (key, value) in headers {
+        write!(out, "{}: {}\r\n", key, value)?;
+    }
+    if let Some(length) = content_length {
+        write!(out, "Content-Length: {}\r\n", length)?;
+    }
+    write!(out, "Connection: close\r\n\r\n")
+}
+
+/// ステータスを説明する短い本文とともに応答します。
+fn send_status(
+    out: &mut impl Write,
+    status: u16,
+    mut headers: Vec<(&str, String)>,
+    head: bool,
+) -> Result<u16, io::Error> {
+    let body = format!("{} {}\n", status, reason(status));
+    headers.retain(|(key, _)| {
+        !key.eq_ignore_ascii_case("Content-Type")
+    });
+    headers.push((
+        "Content-Type",
+        "text/plain; charset=utf-8".into(),
+    ));
+    write_head(out, status, &headers, Some(body.len() as u64))?;
+    if !head {
+        out.write_all(body.as_bytes())?;
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn request(
+        method: &str,
+        target: &str,
+        headers: &[(&str, &str)],
+    ) -> Request {
+        Request {
+            method: method.to_string(),
+            target: target.to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn header<'a>(
+        response: &'a str,
+        name: &str,
+    ) -> Option<&'a str> {
+        response
+            .lines()
+            .take_while(|line| !line.is_empty())
+            .find_map(|line| {
+                line.strip_prefix(&format!("{}: ", name))
+            })
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("bytes=0-9", 100),
+            ByteRange::Partial(0, 9)
+        );
+        assert_eq!(
+            parse_range("bytes=90-", 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range("bytes=-10", 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range("bytes=50-500", 100),
+            ByteRange::Partial(50, 99)
+        );
+        assert_eq!(
+            parse_range("bytes=100-", 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range("bytes=0-1,5-6", 100),
+            ByteRange::Full
+        );
+        assert_eq!(
+            parse_range("bytes=9-0", 100),
+            ByteRange::Full
+        );
+        assert_eq!(
+            parse_range("items=0-9", 100),
+            ByteRange::Full
+        );
+    }
+
+    /// 失敗した場合も削除されるテスト用のディレクトリ
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_respond() -> Result<(), io::Error> {
+        let dir = TempDir(std::env::temp_dir().join(format!(
+            "ipm-http-test-{}-{}",
+            std::process::id(),
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        )));
+        let root = &dir.0;
+        fs::create_dir_all(root.join("packages"))?;
+        fs::write(root.join("repo.yaml"), "author: test\n")?;
+        fs::write(
+            root.join("packages/hello 1.0.ipak"),
+            b"0123456789",
+        )?;
+        let get =
+            |req: Request| -> Result<(u16, String), io::Error> {
+                let mut out = Vec::new();
+                let status = respond(&mut out, root, &req)?;
+                Ok((
+                    status,
+                    String::from_utf8_lossy(&out).into_owned(),
+                ))
+            };
+
+        let (status, response) =
+            get(request("GET", "/repo.yaml", &[]))?;
+        assert_eq!(status, 200);
+        assert_eq!(
+            header(&response, "Content-Type"),
+            Some("application/yaml")
+        );
+        assert!(response.ends_with("\r\n\r\nauthor: test\n"));
+        let etag =
+            header(&response, "ETag").unwrap().to_string();
+
+        let (status, _) = get(request(
+            "GET",
+            "/repo.yaml",
+            &[("if-none-match", &etag)],
+        ))?;
+        assert_eq!(status, 304);
+
+        let target = "/packages/hello%201.0.ipak";
+        let (status, response) = get(request(
+            "GET",
+            target,
+            &[("Range", "bytes=2-4")],
+        ))?;
+        assert_eq!(status, 206);
+        assert_eq!(
+            header(&response, "Content-Range"),
+            Some("bytes 2-4/10")
+        );
+        assert!(response.ends_with("\r\n\r\n234"));
+        // ETagが変わっていれば全体を返す
+        let (status, _) = get(request(
+            "GET",
+            target,
+            &[("Range", "bytes=2-4"), ("If-Range", "\"old\"")],
+        ))?;
+        assert_eq!(status, 200);
+        let (status, _) = get(request(
+            "GET",
+            target,
+            &[("Range", "bytes=10-")],
+        ))?;
+        assert_eq!(status, 416);
+
+        let (status, response) =
+            get(request("HEAD", target, &[]))?;
+        assert_eq!(status, 200);
+        assert_eq!(
+            header(&response, "Content-Length"),
+            Some("10")
+        );
+        assert!(response.ends_with("\r\n\r\n"));
+
+        assert_eq!(
+            get(request("GET", "/../etc/passwd", &[]))?.0,
+            404
+        );
+        assert_eq!(
+            get(request("GET", "/%2e%2e/etc/passwd", &[]))?.0,
+            404
+        );
+        assert_eq!(
+            get(request("GET", "/packages", &[]))?.0,
+            404
+        );
+        assert_eq!(
+            get(request("POST", "/repo.yaml", &[]))?.0,
+            405
+        );
+        Ok(())
+    }
+}
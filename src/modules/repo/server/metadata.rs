@@ -1,4 +1,7 @@
-use crate::modules::repo::{PackageMetaData, RepoData};
+use crate::modules::pkg::relation::ExtraRelationData;
+use crate::modules::repo::{
+    PackageDetails, PackageMetaData, RepoData,
+};
 use ipak::modules::pkg::AuthorAboutData;
 use ipak::modules::project;
 use ipak::utils::files::is_file_exists;
@@ -95,10 +98,21 @@ pub fn metadata() -> Result<RepoData, io::Error> {
                             &project_data.about.package.name,
                             &project_data.about.package.version
                         );
+                        let extra_relation =
+                            ExtraRelationData::from_file(
+                                &entry
+                                    .path()
+                                    .join("ipak/relations.yaml"),
+                            )?;
                         projects.push(PackageMetaData {
                             url,
                             last_modified,
                             info: project_data,
+                            extra_relation,
+                            debian_version: None,
+                            debian_depends: vec![],
+                            multi_arch: None,
+                            details: PackageDetails::default(),
                         });
                     }
 
@@ -119,6 +133,8 @@ pub fn metadata() -> Result<RepoData, io::Error> {
         author: author_about_data,
         last_modified,
         packages: projects,
+        release: None,
+        diagnostics: vec![],
     })
 }
 pub fn show_metadata() -> Result<(), io::Error> {
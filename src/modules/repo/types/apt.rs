@@ -1,8 +1,14 @@
-use super::super::{PackageMetaData, RepoData};
-use crate::utils::www::URL;
+use super::super::diagnostic::{Diagnostic, FieldError};
+use super::super::{
+    PackageDetails, PackageMetaData, RepoData, RepoOptions,
+};
+use crate::modules::pkg::relation::ExtraRelationData;
+use crate::utils::compression;
+use crate::utils::deb822::{self, Fields, Paragraph, Stanza};
+use crate::utils::hash::HashingReader;
+use crate::utils::www::{ToURL, URL};
 use anyhow::{Result, anyhow};
-use chrono::Local;
-use flate2::read::GzDecoder;
+use chrono::{DateTime, Local};
 use ipak::modules::{
     pkg::{
         AboutData, AuthorAboutData, Mode, PackageAboutData,
@@ -10,90 +16,78 @@ use ipak::modules::{
     },
     version::{Version, VersionRange},
 };
-use std::io::{BufRead, BufReader};
-use std::{collections::HashMap, str::FromStr};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::str::FromStr;
+pub mod arch;
+pub mod cache;
+pub mod contents;
+pub mod pdiff;
+pub mod release;
+pub mod signature;
+pub mod source_package;
+pub mod sources;
+pub mod translation;
+pub mod version;
+use cache::IndexCache;
+use pdiff::DiffIndex;
+use release::Release;
+use source_package::SourcePackage;
+use version::{
+    DebianVersion, Dependency, Relation, VersionConstraint,
+};
 
-/// APTパッケージのcontrolファイルを解析し、HashMapに変換します。
+/// APTパッケージのcontrolファイル(deb822形式の1つの段落)を解析します。
 pub fn parse_control_file(
     control_content: &str,
-) -> Result<HashMap<String, String>> {
-    let mut data: HashMap<String, String> = HashMap::new();
-    let mut current_key = String::new();
-    let mut current_value = String::new();
-    let mut is_multiline = false;
-
-    for line in control_content.lines() {
-        if line.starts_with(' ') || line.starts_with('\t') {
-            // マルチラインの値の続き
-            if is_multiline {
-                current_value.push('\n');
-                current_value.push_str(line.trim());
-            }
-            continue;
-        }
-        // 新しいキー:値 のペアが始まる場合、前のものを保存
-        if !current_key.is_empty() {
-            data.insert(
-                current_key.clone(),
-                current_value.clone(),
-            );
-        }
-        if let Some(index) = line.find(':') {
-            current_key = line[..index].trim().to_string();
-            current_value = line[index + 1..].trim().to_string();
-            is_multiline = current_value.is_empty();
-        } else {
-            current_key.clear();
-            current_value.clear();
-            is_multiline = false;
-        }
-    }
-    // 最後のキー:値 を保存
-    if !current_key.is_empty() {
-        data.insert(current_key, current_value);
-    }
+) -> Result<Paragraph> {
+    Ok(Paragraph::parse(control_content)?)
+}
+
+/// 必須のフィールドを取り出します。なければ[`FieldError`]を返します。
+fn required_field<'a>(
+    data: &'a impl Fields,
+    name: &str,
+) -> Result<Cow<'a, str>> {
+    data.field(name).ok_or_else(|| {
+        FieldError::new(name, "field not found").into()
+    })
+}
 
-    Ok(data)
+/// フィールド`name`の解析のエラーを、フィールド名の付いた[`FieldError`]にします。
+fn in_field<T>(name: &str, result: Result<T>) -> Result<T> {
+    result.map_err(|e| FieldError::new(name, e).into())
 }
 
-/// 解析されたHashMapからPackageData構造体を生成します。
+/// 解析されたcontrolファイルからPackageData構造体を生成します。
 pub fn to_package_data(
-    data: HashMap<String, String>,
+    data: &impl Fields,
 ) -> Result<PackageData> {
-    let package_name = data
-        .get("Package")
-        .ok_or_else(|| {
-            anyhow!("'Package' field not found in control file")
-        })?
-        .to_string();
-    let version_str = data.get("Version").ok_or_else(|| {
-        anyhow!("'Version' field not found in control file")
-    })?;
-    let version =
-        Version::from_str(version_str).map_err(|e| {
-            anyhow!(
-                "Failed to parse version '{}': {}",
-                version_str,
-                e
-            )
-        })?;
-    let description = data
-        .get("Description")
-        .unwrap_or(&String::new())
-        .to_string();
+    let package_name =
+        required_field(data, "Package")?.to_string();
+    let version_str = required_field(data, "Version")?;
+    let version = in_field(
+        "Version",
+        DebianVersion::from_str(&version_str)
+            .and_then(|v| to_ipak_version(&v)),
+    )?;
+    let description = description_from_field(
+        &data.field("Description").unwrap_or_default(),
+    );
     let architecture = data
-        .get("Architecture")
+        .field("Architecture")
         .map(|s| {
             s.split(',')
                 .map(|arch| arch.trim().to_string())
                 .collect()
         })
         .unwrap_or_default();
-    let empty_string = String::new();
     let maintainer_str =
-        data.get("Maintainer").unwrap_or(&empty_string);
+        data.field("Maintainer").unwrap_or_default();
     let (author_name, author_email) =
-        parse_maintainer(maintainer_str);
+        parse_maintainer(&maintainer_str);
     let author_data = AuthorAboutData {
         name: author_name,
         email: author_email,
@@ -102,29 +96,48 @@ pub fn to_package_data(
     let mut relation_data = RelationData::default();
 
     // Dependencies (Depends)
-    if let Some(depends_str) = data.get("Depends") {
-        relation_data.depend =
-            parse_package_ranges(depends_str)?;
+    if let Some(depends_str) = data.field("Depends") {
+        relation_data.depend = in_field(
+            "Depends",
+            parse_package_ranges(&depends_str),
+        )?;
+    }
+    // Pre-Dependsはインストール前に満たす必要があるため、依存関係の先頭に加える
+    if let Some(pre_depends_str) = data.field("Pre-Depends") {
+        let mut depend = in_field(
+            "Pre-Depends",
+            parse_package_ranges(&pre_depends_str),
+        )?;
+        depend.append(&mut relation_data.depend);
+        relation_data.depend = depend;
     }
     // Suggests
-    if let Some(suggests_str) = data.get("Suggests") {
-        relation_data.suggests =
-            parse_package_ranges(suggests_str)?;
+    if let Some(suggests_str) = data.field("Suggests") {
+        relation_data.suggests = in_field(
+            "Suggests",
+            parse_package_ranges(&suggests_str),
+        )?;
     }
     // Recommends
-    if let Some(recommends_str) = data.get("Recommends") {
-        relation_data.recommends =
-            parse_package_ranges(recommends_str)?;
+    if let Some(recommends_str) = data.field("Recommends") {
+        relation_data.recommends = in_field(
+            "Recommends",
+            parse_package_ranges(&recommends_str),
+        )?;
     }
     // Conflicts
-    if let Some(conflicts_str) = data.get("Conflicts") {
-        relation_data.conflicts =
-            parse_single_package_ranges(conflicts_str)?;
+    if let Some(conflicts_str) = data.field("Conflicts") {
+        relation_data.conflicts = in_field(
+            "Conflicts",
+            parse_single_package_ranges(&conflicts_str),
+        )?;
     }
     // Provides (Virtual Packages)
-    if let Some(provides_str) = data.get("Provides") {
-        relation_data.virtuals =
-            parse_package_versions(provides_str)?;
+    if let Some(provides_str) = data.field("Provides") {
+        relation_data.virtuals = in_field(
+            "Provides",
+            parse_package_versions(&provides_str),
+        )?;
     }
 
     Ok(PackageData {
@@ -142,57 +155,220 @@ pub fn to_package_data(
     })
 }
 
+/// 解析されたcontrolファイルからRelationDataで表現できない依存関係を取り出します。
+pub fn to_extra_relation_data(
+    data: &impl Fields,
+) -> Result<ExtraRelationData> {
+    let mut extra = ExtraRelationData::default();
+    if let Some(pre_depends_str) = data.field("Pre-Depends") {
+        extra.pre_depends = in_field(
+            "Pre-Depends",
+            parse_package_ranges(&pre_depends_str),
+        )?;
+    }
+    if let Some(breaks_str) = data.field("Breaks") {
+        extra.breaks = in_field(
+            "Breaks",
+            parse_single_package_ranges(&breaks_str),
+        )?;
+    }
+    if let Some(replaces_str) = data.field("Replaces") {
+        extra.replaces = in_field(
+            "Replaces",
+            parse_single_package_ranges(&replaces_str),
+        )?;
+    }
+    if let Some(enhances_str) = data.field("Enhances") {
+        extra.enhances = in_field(
+            "Enhances",
+            parse_package_ranges(&enhances_str),
+        )?;
+    }
+    Ok(extra)
+}
+
+/// Packagesインデックスの段落から、サイズやSectionなどの付加情報を取り出します。
+pub fn to_package_details(data: &impl Fields) -> PackageDetails {
+    let text =
+        |key: &str| data.field(key).map(|v| v.into_owned());
+    let number =
+        |key: &str| data.field(key).and_then(|v| v.parse().ok());
+    PackageDetails {
+        size: number("Size"),
+        installed_size: number("Installed-Size"),
+        sha256: text("SHA256"),
+        section: text("Section"),
+        priority: text("Priority"),
+        homepage: text("Homepage"),
+        tags: data
+            .field("Tag")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect(),
+    }
+}
+
+/// PackageDataからdeb822形式のcontrolファイルを生成します。
+///
+/// `extra`のPre-Dependsに含まれる依存関係はDependsからは除きます。
+pub fn to_control_file(
+    package_data: &PackageData,
+    extra: &ExtraRelationData,
+    installed_size: Option<u64>,
+) -> String {
+    let about = &package_data.about;
+    let relation = &package_data.relation;
+    let pre_depends = format_package_ranges(&extra.pre_depends);
+    let pre_depends_entries: Vec<&str> =
+        pre_depends.split(", ").collect();
+    let depends = format_package_ranges(&relation.depend)
+        .split(", ")
+        .filter(|entry| !pre_depends_entries.contains(entry))
+        .collect::<Vec<&str>>()
+        .join(", ");
+    let mut fields: Vec<(&str, String)> = vec![
+        ("Package", about.package.name.clone()),
+        ("Version", about.package.version.to_string()),
+        (
+            "Architecture",
+            debian_architecture(&package_data.architecture),
+        ),
+    ];
+    let maintainer = if about.author.email.is_empty() {
+        about.author.name.clone()
+    } else {
+        format!("{} <{}>", about.author.name, about.author.email)
+    };
+    fields.push(("Maintainer", maintainer));
+    if let Some(size) = installed_size {
+        fields.push(("Installed-Size", size.to_string()));
+    }
+    let relation_fields = [
+        ("Pre-Depends", pre_depends),
+        ("Depends", depends),
+        (
+            "Recommends",
+            format_package_ranges(&relation.recommends),
+        ),
+        ("Suggests", format_package_ranges(&relation.suggests)),
+        ("Enhances", format_package_ranges(&extra.enhances)),
+        ("Breaks", format_single_package_ranges(&extra.breaks)),
+        (
+            "Conflicts",
+            format_single_package_ranges(&relation.conflicts),
+        ),
+        (
+            "Replaces",
+            format_single_package_ranges(&extra.replaces),
+        ),
+        (
+            "Provides",
+            format_package_versions(&relation.virtuals),
+        ),
+    ];
+    for (key, value) in relation_fields {
+        if !value.is_empty() {
+            fields.push((key, value));
+        }
+    }
+
+    let mut control = Paragraph::new();
+    for (key, value) in fields {
+        control.set(key, value);
+    }
+    control.set(
+        "Description",
+        description_to_field(&about.package.description),
+    );
+    control.to_string()
+}
+
+/// Descriptionフィールドの値(概要と" ."で段落を区切った詳細)を、
+/// 空行で段落を区切ったテキストに変換します。
+fn description_from_field(value: &str) -> String {
+    value
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            if index > 0 && line.trim() == "." {
+                ""
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// `description_from_field`の逆変換を行います。
+fn description_to_field(description: &str) -> String {
+    description
+        .trim()
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            if index == 0 {
+                line.trim()
+            } else if line.trim().is_empty() {
+                "."
+            } else {
+                line.trim_end()
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// ipakのアーキテクチャ名をDebianのアーキテクチャ名に変換します。
+/// 複数指定されている場合は先頭のものを使い、指定がなければ"all"とします。
+pub fn debian_architecture(architecture: &[String]) -> String {
+    match architecture.first().map(|s| s.as_str()) {
+        None | Some("any") | Some("all") => "all".to_string(),
+        Some("x86_64") => "amd64".to_string(),
+        Some("aarch64") => "arm64".to_string(),
+        Some("x86") | Some("i686") => "i386".to_string(),
+        Some("powerpc64") => "ppc64el".to_string(),
+        Some("riscv64") => "riscv64".to_string(),
+        Some(arch) => arch.to_string(),
+    }
+}
+
 /// Maintainer文字列から名前とメールアドレスをパースします。
 /// 例: "John Doe <john.doe@example.com>"
-fn parse_maintainer(maintainer_str: &str) -> (String, String) {
-    if let Some(start) = maintainer_str.find('<') {
-        if let Some(end) = maintainer_str.find('>') {
-            let name =
-                maintainer_str[..start].trim().to_string();
-            let email = maintainer_str[start + 1..end]
-                .trim()
-                .to_string();
-            return (name, email);
-        }
+pub fn parse_maintainer(
+    maintainer_str: &str,
+) -> (String, String) {
+    if let Some(start) = maintainer_str.find('<')
+        && let Some(end) = maintainer_str.find('>')
+    {
+        let name = maintainer_str[..start].trim().to_string();
+        let email =
+            maintainer_str[start + 1..end].trim().to_string();
+        return (name, email);
     }
     // メールアドレスがない場合
     (maintainer_str.to_string(), String::new())
 }
 
 /// 依存関係の文字列（例: "pkg-a (>= 1.0) | pkg-b, pkg-c (<< 2.0)"）をパースします。
+/// ホストで有効でない選択肢は除き、選択肢が残らない依存関係は含めません。
 fn parse_package_ranges(
     input: &str,
 ) -> Result<Vec<Vec<PackageRange>>> {
     let mut result = Vec::new();
     for group_str in input.split(',') {
-        let alternatives: Result<Vec<PackageRange>> = group_str
+        let alternatives = group_str
             .split('|')
-            .map(|alt_str| {
-                let parts: Vec<&str> = alt_str.split_whitespace().collect();
-                if parts.is_empty() {
-                    return Err(anyhow!("Empty package range alternative"));
-                }
-                let name = parts[0].to_string();
-                let range = if parts.len() > 1 {
-                    let version_range_str = parts[1..].join(" ");
-                    let version_range_str = version_range_str
-                        .trim_start_matches('(')
-                        .trim_end_matches(')');
-                    VersionRange::from_str(version_range_str).map_err(|e| {
-                        anyhow!(
-                            "Failed to parse version range '{}' for package '{}': {}",
-                            version_range_str,
-                            name,
-                            e
-                        )
-                    })?
-                } else {
-                    VersionRange::default()
-                };
-                Ok(PackageRange { name, range })
-            })
-            .collect();
-        result.push(alternatives?);
+            .map(parse_package_range)
+            .collect::<Result<Vec<Option<PackageRange>>>>()?;
+        let alternatives: Vec<PackageRange> =
+            alternatives.into_iter().flatten().collect();
+        if !alternatives.is_empty() {
+            result.push(alternatives);
+        }
     }
     Ok(result)
 }
@@ -201,170 +377,1414 @@ fn parse_package_ranges(
 fn parse_single_package_ranges(
     input: &str,
 ) -> Result<Vec<PackageRange>> {
-    input
+    let ranges = input
         .split(',')
-        .map(|s| {
-            let parts: Vec<&str> = s.split_whitespace().collect();
-            if parts.is_empty() {
-                return Err(anyhow!("Empty package range"));
-            }
-            let name = parts[0].to_string();
-            let range = if parts.len() > 1 {
-                let version_range_str = parts[1..].join(" ");
-                let version_range_str = version_range_str
-                    .trim_start_matches('(')
-                    .trim_end_matches(')');
-                VersionRange::from_str(version_range_str).map_err(|e| {
-                    anyhow!(
-                        "Failed to parse version range '{}' for package '{}': {}",
-                        version_range_str,
-                        name,
-                        e
-                    )
-                })?
-            } else {
-                VersionRange::default()
-            };
-            Ok(PackageRange { name, range })
-        })
-        .collect()
+        .map(parse_package_range)
+        .collect::<Result<Vec<Option<PackageRange>>>>(
+    )?;
+    Ok(ranges.into_iter().flatten().collect())
 }
 
-/// virtualパッケージの文字列（例: "pkg-virtual (1.0), another-virtual"）をパースします。
+/// virtualパッケージの文字列（例: "pkg-virtual (= 1.0), another-virtual"）をパースします。
 fn parse_package_versions(
     input: &str,
 ) -> Result<Vec<PackageVersion>> {
-    input
+    let mut result = Vec::new();
+    for s in input.split(',') {
+        let Some((name, constraint)) = parse_relation_entry(s)?
+        else {
+            continue;
+        };
+        let version = match constraint {
+            Some(constraint) => {
+                to_ipak_version(&constraint.version)?
+            }
+            None => Version::default(),
+        };
+        result.push(PackageVersion { name, version });
+    }
+    Ok(result)
+}
+
+/// 依存関係の1つのエントリ
+/// （例: "libfoo:any (>= 1.0) [amd64 arm64] <!nocheck>"）を、名前とバージョンの制約に分けます。
+///
+/// `:any`や`:native`などのアーキテクチャ修飾子は名前から取り除きます。
+/// アーキテクチャやビルドプロファイルの制限がホストで有効でない場合は`None`を返します。
+fn parse_relation_entry(
+    input: &str,
+) -> Result<Option<(String, Option<VersionConstraint>)>> {
+    let input = input.trim();
+    let mut rest = input;
+    // ビルドプロファイル ("<!nocheck>"など、複数書ける)
+    let mut profiles = Vec::new();
+    while let Some(stripped) = rest.strip_suffix('>') {
+        let start = stripped.rfind('<').ok_or_else(|| {
+            anyhow!("Unterminated build profile in '{}'", input)
+        })?;
+        profiles.push(&stripped[start + 1..]);
+        rest = stripped[..start].trim_end();
+    }
+    // アーキテクチャの制限 ("[amd64 arm64]", "[!i386]")
+    let mut restriction = None;
+    if let Some(stripped) = rest.strip_suffix(']') {
+        let start = stripped.rfind('[').ok_or_else(|| {
+            anyhow!(
+                "Unterminated architecture list in '{}'",
+                input
+            )
+        })?;
+        restriction = Some(&stripped[start + 1..]);
+        rest = stripped[..start].trim_end();
+    }
+    let (name, constraint) = match rest.find('(') {
+        Some(index) => (&rest[..index], Some(&rest[index..])),
+        None => (rest, None),
+    };
+    // "libfoo:any"の":any"はどのアーキテクチャのパッケージでもよいことを表す
+    let name = name.trim().split(':').next().unwrap_or_default();
+    if name.is_empty() {
+        return Err(anyhow!(
+            "Empty package name in '{}'",
+            input
+        ));
+    }
+    if !restriction.is_none_or(arch::restriction_applies)
+        || !arch::profiles_apply(&profiles)
+    {
+        return Ok(None);
+    }
+    let constraint = constraint
+        .map(|c| {
+            VersionConstraint::from_str(c).map_err(|e| {
+                anyhow!(
+                    "Failed to parse version range '{}' for package '{}': {}",
+                    c,
+                    name,
+                    e
+                )
+            })
+        })
+        .transpose()?;
+    Ok(Some((name.to_string(), constraint)))
+}
+
+fn parse_package_range(
+    input: &str,
+) -> Result<Option<PackageRange>> {
+    let Some((name, constraint)) = parse_relation_entry(input)?
+    else {
+        return Ok(None);
+    };
+    let range = match constraint {
+        Some(constraint) => to_ipak_range(&constraint)?,
+        None => VersionRange::default(),
+    };
+    Ok(Some(PackageRange { name, range }))
+}
+
+/// ipakのVersionとして解釈を試みる文字列の候補を返します。
+/// dpkg形式の全体を解釈できない場合に備え、upstream_versionの
+/// 先頭の数字と"."からなる部分も候補に含めます。
+///
+/// 表示用のバージョンにのみ使い、制約には使いません。
+fn ipak_version_candidates(
+    version: &DebianVersion,
+) -> Vec<String> {
+    let numeric: String = version
+        .upstream
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    let mut candidates = vec![version.to_string()];
+    let numeric = numeric.trim_end_matches('.');
+    if !numeric.is_empty() && numeric != candidates[0] {
+        candidates.push(numeric.to_string());
+    }
+    candidates
+}
+
+/// DebianのバージョンをipakのVersionに変換します。
+/// 比較にはDebianVersionを使い、こちらは表示やipakとの互換のために使います。
+pub fn to_ipak_version(
+    version: &DebianVersion,
+) -> Result<Version> {
+    ipak_version_candidates(version)
+        .iter()
+        .find_map(|v| Version::from_str(v).ok())
+        .ok_or_else(|| {
+            anyhow!("Failed to parse version '{}'", version)
+        })
+}
+
+/// Debianのバージョンの制約をipakのVersionRangeに変換します。
+///
+/// 一部を切り捨てたバージョンで置き換えると制約が広がったり
+/// 狭まったりするため、そのまま表せない場合はエラーを返します。
+fn to_ipak_range(
+    constraint: &VersionConstraint,
+) -> Result<VersionRange> {
+    let op = match constraint.relation {
+        Relation::Earlier => "<",
+        Relation::EarlierOrEqual => "<=",
+        Relation::Exactly => "=",
+        Relation::LaterOrEqual => ">=",
+        Relation::Later => ">",
+    };
+    VersionRange::from_str(&format!(
+        "{} {}",
+        op, constraint.version
+    ))
+    .map_err(|e| {
+        anyhow!(
+            "Version range '{}' cannot be represented in ipak: {}",
+            constraint,
+            e
+        )
+    })
+}
+
+/// 依存関係の文字列をパースし、バージョンの制約をそのまま保持します。
+fn parse_dependencies(
+    input: &str,
+) -> Result<Vec<Vec<Dependency>>> {
+    let mut result = Vec::new();
+    for group_str in input.split(',') {
+        let mut alternatives = Vec::new();
+        for entry in group_str.split('|') {
+            if let Some((name, constraint)) =
+                parse_relation_entry(entry)?
+            {
+                alternatives
+                    .push(Dependency { name, constraint });
+            }
+        }
+        if !alternatives.is_empty() {
+            result.push(alternatives);
+        }
+    }
+    Ok(result)
+}
+
+/// VersionRangeをDebianの関係演算子付きの制約（例: "(>= 1.0)"）に変換します。
+/// 制約がない場合は空のVecを返します。
+fn format_version_constraints(
+    range: &VersionRange,
+) -> Vec<String> {
+    range
+        .to_string()
         .split(',')
-        .map(|s| {
-            let parts: Vec<&str> = s.split_whitespace().collect();
-            if parts.is_empty() {
-                return Err(anyhow!("Empty package version"));
+        .filter_map(|constraint| {
+            let constraint = constraint.trim();
+            if constraint.is_empty() || constraint == "*" {
+                return None;
             }
-            let name = parts[0].to_string();
-            let version = if parts.len() > 1 {
-                let version_str = parts[1..].join(" ");
-                let version_str = version_str
-                    .trim_start_matches('(')
-                    .trim_end_matches(')');
-                Version::from_str(version_str).map_err(|e| {
-                    anyhow!(
-                        "Failed to parse version '{}' for package '{}': {}",
-                        version_str,
-                        name,
-                        e
-                    )
-                })?
-            } else {
-                Version::default()
+            let split_at = constraint
+                .find(|c: char| {
+                    !matches!(c, '<' | '>' | '=' | '!')
+                })
+                .unwrap_or(constraint.len());
+            let (op, version) = constraint.split_at(split_at);
+            let op = match op {
+                ">" => ">>",
+                "<" => "<<",
+                "==" | "" => "=",
+                op => op,
             };
-            Ok(PackageVersion { name, version })
+            Some(format!("({} {})", op, version.trim()))
         })
         .collect()
 }
 
-/// Helper function to extract filename from control content.
-/// This is a simplified version and might need refinement based on actual control file content.
-fn get_filename(control_content: &str) -> String {
-    let mut filename = String::new();
-    for line in control_content.lines() {
-        if let Some(stripped) = line.strip_prefix("Filename:") {
-            filename = stripped.trim().to_string();
-            break;
+/// PackageRangeを"name (op version)"形式に変換します。
+/// 複数の制約を持つ場合は、それぞれを別のエントリとして返します。
+fn format_package_range(package: &PackageRange) -> Vec<String> {
+    let constraints = format_version_constraints(&package.range);
+    if constraints.is_empty() {
+        vec![package.name.clone()]
+    } else {
+        constraints
+            .into_iter()
+            .map(|c| format!("{} {}", package.name, c))
+            .collect()
+    }
+}
+
+/// 依存関係を"pkg-a (>= 1.0) | pkg-b, pkg-c"形式に変換します。
+fn format_package_ranges(
+    groups: &[Vec<PackageRange>],
+) -> String {
+    let mut entries = Vec::new();
+    for group in groups {
+        if let [single] = group.as_slice() {
+            // 単一の依存先であれば、複数の制約を別エントリとして表現できる
+            entries.extend(format_package_range(single));
+        } else {
+            // 選択肢の中では最初の制約のみを使う
+            let alternatives: Vec<String> = group
+                .iter()
+                .filter_map(|alt| {
+                    format_package_range(alt).into_iter().next()
+                })
+                .collect();
+            entries.push(alternatives.join(" | "));
+        }
+    }
+    entries.join(", ")
+}
+
+/// 単一の依存関係を"pkg-a (>= 1.0), pkg-b"形式に変換します。
+fn format_single_package_ranges(
+    packages: &[PackageRange],
+) -> String {
+    packages
+        .iter()
+        .flat_map(format_package_range)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// virtualパッケージを"pkg-virtual (= 1.0), another-virtual"形式に変換します。
+fn format_package_versions(
+    packages: &[PackageVersion],
+) -> String {
+    packages
+        .iter()
+        .map(|p| {
+            let version = p.version.to_string();
+            if version.is_empty()
+                || version == Version::default().to_string()
+            {
+                p.name.clone()
+            } else {
+                format!("{} (= {})", p.name, version)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// APTリポジトリ内のインデックスの位置
+struct IndexLocation {
+    /// `dists/`と`pool/`を含むアーカイブのルート (例: `.../ubuntu/`)
+    archive_root: URL,
+    /// `Release`の置かれたスイートのディレクトリ (例: `.../dists/noble/`)
+    suite_url: URL,
+    /// スイートのディレクトリからの相対パス (例: `main/binary-amd64`)。
+    /// フラットなリポジトリでは空です。
+    index_dir: String,
+}
+
+impl IndexLocation {
+    /// Packagesのあるディレクトリ(例: `.../dists/noble/main/binary-amd64/`)から
+    /// 各ディレクトリを求めます。
+    ///
+    /// アーカイブのルートは`archive_root`で明示されていればそれを使い、
+    /// なければ`dists/`の親ディレクトリとします。
+    ///
+    /// フラットなリポジトリ(`deb https://host/path ./`)では、`url`に
+    /// `Release`と`Packages`が直接置かれ、アーカイブのルートも`url`とします。
+    fn new(
+        url: &URL,
+        archive_root: Option<&str>,
+        flat: bool,
+    ) -> Result<Self, std::io::Error> {
+        let components: Vec<String> = url
+            .path()
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .filter(|c| c != "/")
+            .collect();
+        let dists = components.iter().position(|c| c == "dists");
+        let depth = match dists {
+            _ if flat => components.len(),
+            Some(index) => (index + 2).min(components.len()),
+            // "<component>/binary-<arch>"の2階層上とみなす
+            None => components.len().saturating_sub(2),
+        };
+        let sub_url = |end: usize| {
+            URL::new(
+                url.protocol(),
+                url.domain(),
+                components[..end].iter().collect(),
+            )
+        };
+        let archive_root = match archive_root {
+            Some(root) => root.to_url().map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid archive root '{}': {}",
+                        root, e
+                    ),
+                )
+            })?,
+            None => match dists {
+                _ if flat => url.clone(),
+                Some(index) => sub_url(index),
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "Cannot find the archive root of {}: the URL has no dists/ directory and no archive-root is configured",
+                            url
+                        ),
+                    ));
+                }
+            },
+        };
+        Ok(Self {
+            archive_root,
+            suite_url: sub_url(depth),
+            index_dir: components[depth..].join("/"),
+        })
+    }
+    /// インデックスのディレクトリにある`name`の、スイートのディレクトリからの相対パス
+    fn index_path(&self, name: &str) -> String {
+        if self.index_dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.index_dir, name)
+        }
+    }
+
+    /// インデックスのコンポーネント (例: `main`)
+    fn component(&self) -> &str {
+        self.index_dir
+            .rsplit_once('/')
+            .map(|(component, _)| component)
+            .unwrap_or_default()
+    }
+}
+
+/// 圧縮形式ごとのインデックスファイルの拡張子 (優先する順)
+const INDEX_SUFFIXES: [&str; 5] =
+    [".xz", ".zst", ".bz2", ".gz", ""];
+
+/// `Release`に記載されたインデックス(`path`に拡張子を付けたもの)のうち、
+/// 最も圧縮率の高いものをダウンロード・検証し、展開した内容を先頭から読めるファイルとして返します。
+/// 取得や検証に失敗した場合は、次の候補を試します。
+///
+/// 展開した内容はキャッシュに直接書き出し、メモリには保持しません。
+/// `Release`に記載された展開後のSHA256とキャッシュが一致すればダウンロードしません。
+/// 一致しない場合は、`<path>.diff/Index`の差分(pdiff)があれば、
+/// 差分のみを取得してキャッシュを更新します。
+///
+/// 差分の適用や候補の取得に失敗した場合は、`repo`の問題として`diagnostics`に加えます。
+fn fetch_index(
+    suite_url: &URL,
+    release: &Release,
+    path: &str,
+    repo: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<File, std::io::Error> {
+    let candidates: Vec<String> = INDEX_SUFFIXES
+        .iter()
+        .map(|suffix| format!("{}{}", path, suffix))
+        .filter(|name| release.file(name).is_some())
+        .collect();
+    if candidates.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} is not listed in Release", path),
+        ));
+    }
+    let cache = IndexCache::new(suite_url, path);
+    if let Some(sha256) = cache.sha256() {
+        if release.file(path).is_some_and(|f| f.sha256 == sha256)
+            && let Some(file) = cache.open()
+        {
+            return Ok(file);
+        }
+        match update_with_pdiff(
+            suite_url,
+            release,
+            path,
+            &cache,
+            &sha256,
+            repo,
+            diagnostics,
+        ) {
+            Ok(Some(index)) => {
+                cache.write(&index, repo, diagnostics);
+                if let Some(file) = cache.open() {
+                    return Ok(file);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => diagnostics.push(Diagnostic::new(
+                repo,
+                format!(
+                    "Failed to update {} with pdiffs: {}",
+                    path, e
+                ),
+            )),
+        }
+    }
+    // 後の候補で取得できた場合のみ、それまでの失敗を`diagnostics`に加える
+    let mut errors: Vec<std::io::Error> = Vec::new();
+    for name in candidates {
+        match cache.store(repo, diagnostics, |file| {
+            download(suite_url, release, &name, file)
+        }) {
+            Ok(file) => {
+                diagnostics.extend(
+                    errors
+                        .iter()
+                        .map(|e| Diagnostic::new(repo, e)),
+                );
+                return Ok(file);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    let kind = errors.last().unwrap().kind();
+    let messages: Vec<String> =
+        errors.iter().map(|e| e.to_string()).collect();
+    Err(std::io::Error::new(kind, messages.join("; ")))
+}
+
+/// `Release`に記載された`name`をダウンロードしながら展開し、`out`に書き出します。
+/// ダウンロードしたファイルは、記載されたサイズとSHA256で検証します。
+///
+/// 記載されたサイズを超えて読み込まず、展開後のファイルも記載されていれば
+/// そのサイズを超えた時点で失敗とします(展開すると巨大になるファイルへの対策)。
+///
+/// `Acquire-By-Hash`が有効な場合は、ミラーの更新中でも内容の変わらない
+/// `by-hash/SHA256/<hash>`から取得し、失敗すれば`name`から取得します。
+fn download(
+    suite_url: &URL,
+    release: &Release,
+    name: &str,
+    out: &mut File,
+) -> Result<(), std::io::Error> {
+    let file = release.file(name).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} is not listed in Release", name),
+        )
+    })?;
+    let plain_name = INDEX_SUFFIXES
+        .iter()
+        .filter(|suffix| !suffix.is_empty())
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name);
+    let plain_size = release.file(plain_name).map(|f| f.size);
+    let mut paths = Vec::new();
+    if release.acquire_by_hash {
+        let dir = name
+            .rsplit_once('/')
+            .map(|(dir, _)| format!("{}/", dir))
+            .unwrap_or_default();
+        paths.push(format!(
+            "{}by-hash/SHA256/{}",
+            dir, file.sha256
+        ));
+    }
+    paths.push(name.to_string());
+    let too_large = |size: u64| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "{} is larger than listed in Release ({} bytes)",
+                name, size
+            ),
+        )
+    };
+    let mut last_error = None;
+    for path in paths {
+        let result = (|| -> Result<(), std::io::Error> {
+            out.set_len(0)?;
+            out.rewind()?;
+            let body =
+                suite_url.clone().join(&path)?.open().map_err(
+                    |e| {
+                        std::io::Error::other(format!(
+                            "Failed to fetch {}: {}",
+                            path, e
+                        ))
+                    },
+                )?;
+            let mut reader =
+                HashingReader::new(body.take(file.size + 1));
+            let decoder =
+                compression::decoder(name, &mut reader)?;
+            match plain_size {
+                Some(limit) => {
+                    let written = std::io::copy(
+                        &mut decoder.take(limit + 1),
+                        out,
+                    )?;
+                    if written > limit {
+                        return Err(too_large(limit));
+                    }
+                }
+                None => {
+                    std::io::copy(&mut { decoder }, out)?;
+                }
+            }
+            // Releaseに記載されたサイズとSHA256で改ざんや欠損を確認
+            let (sha256, size) = reader.finish()?;
+            if size > file.size {
+                return Err(too_large(file.size));
+            }
+            release.verify_digest(name, &sha256, size)
+        })();
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) => last_error = Some(e),
         }
     }
-    filename
+    Err(last_error.unwrap())
+}
+
+/// ファイルの内容を文字列として読み込みます。
+/// UTF-8として正しくない部分は置き換えます。
+fn read_lossy(mut file: File) -> Result<String, std::io::Error> {
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    Ok(String::from_utf8(content).unwrap_or_else(|e| {
+        String::from_utf8_lossy(e.as_bytes()).into_owned()
+    }))
 }
 
-/// 指定されたURLからPackages.gzファイルをダウンロードし、解析してRepoDataを返します。
-pub fn fetch(url: URL) -> Result<RepoData, std::io::Error> {
-    // URLに"Packages.gz"を結合
-    let packages_url =
-        url.clone().join("Packages.gz").map_err(|e| {
+/// `<path>.diff/Index`に記載された差分(pdiff)を`cached`に順に適用し、
+/// 最新のインデックスを返します。
+///
+/// 差分が提供されていない場合や、キャッシュが古く差分で更新できない場合は`None`を返します。
+fn update_with_pdiff(
+    suite_url: &URL,
+    release: &Release,
+    path: &str,
+    cache: &IndexCache,
+    cached_sha256: &str,
+    repo: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Option<Vec<u8>>, std::io::Error> {
+    let index_name = format!("{}.diff/Index", path);
+    if release.file(&index_name).is_none() {
+        return Ok(None);
+    }
+    let index = IndexCache::new(suite_url, &index_name).store(
+        repo,
+        diagnostics,
+        |file| download(suite_url, release, &index_name, file),
+    )?;
+    let index =
+        DiffIndex::parse(&read_lossy(index)?).map_err(|e| {
             std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
-                e,
+                format!("{}: {}", index_name, e),
             )
         })?;
+    let Some(patches) = index.patches_from(cached_sha256) else {
+        return Ok(None);
+    };
+    // edの差分は行単位で書き換えるため、キャッシュ全体を読み込む
+    let Some(mut content) = cache.read() else {
+        return Ok(None);
+    };
+    for patch in patches {
+        let file =
+            index.download_of(patch).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "No download listed for pdiff {}",
+                        patch.name
+                    ),
+                )
+            })?;
+        let compressed = suite_url
+            .clone()
+            .join(&format!("{}.diff/{}", path, file.name))?
+            .fetch_bin()
+            .map_err(|e| {
+                std::io::Error::other(format!(
+                    "Failed to fetch pdiff {}: {}",
+                    file.name, e
+                ))
+            })?;
+        file.verify(&compressed)?;
+        let mut text = String::new();
+        compression::decoder(&file.name, &compressed[..])?
+            .read_to_string(&mut text)?;
+        patch.verify(text.as_bytes())?;
+        content = pdiff::apply_ed(&content, &text)?;
+    }
+    index.current.verify(&content)?;
+    Ok(Some(content))
+}
 
-    // HTTPリクエストでPackages.gzをダウンロード
-    let response_text =
-        packages_url.fetch_bin().map_err(|e| {
+/// Packagesのあるディレクトリ(例: `.../dists/noble/main/binary-amd64/`)に対応する
+/// `Contents-<arch>`をダウンロード・検証し、展開した内容を先頭から読めるファイルとして返します。
+///
+/// Contentsは大きいため、Packagesとは別に必要な場合にのみ取得し、メモリには読み込みません。
+/// コンポーネントごとの`main/Contents-amd64`がなければ、
+/// スイート全体の`Contents-amd64`を使います。
+pub fn fetch_contents(
+    url: URL,
+    options: &RepoOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<BufReader<File>, std::io::Error> {
+    let location = IndexLocation::new(
+        &url,
+        options.archive_root.as_deref(),
+        options.flat,
+    )?;
+    let (component, binary_dir) = location
+        .index_dir
+        .rsplit_once('/')
+        .unwrap_or(("", &location.index_dir));
+    let arch =
+        binary_dir.strip_prefix("binary-").ok_or_else(|| {
             std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to fetch Packages.gz: {}", e),
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a binary package index", url),
             )
         })?;
+    let release =
+        Release::fetch(&location.suite_url, &options.signature)?;
+    let mut paths = Vec::new();
+    if !component.is_empty() {
+        paths.push(format!("{}/Contents-{}", component, arch));
+    }
+    paths.push(format!("Contents-{}", arch));
+    let repo = url.to_string();
+    let mut last_error = None;
+    for path in &paths {
+        match fetch_index(
+            &location.suite_url,
+            &release,
+            path,
+            &repo,
+            diagnostics,
+        ) {
+            Ok(index) => return Ok(BufReader::new(index)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap())
+}
+
+/// ソースパッケージのインデックスのあるディレクトリ
+/// (例: `.../dists/noble/main/source/`)から`Sources`をダウンロード・検証し、
+/// 解析したソースパッケージを返します。
+/// 解析できない段落は飛ばし、`diagnostics`に加えます。
+pub fn fetch_sources(
+    url: URL,
+    options: &RepoOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<SourcePackage>, std::io::Error> {
+    let location = IndexLocation::new(
+        &url,
+        options.archive_root.as_deref(),
+        options.flat,
+    )?;
+    let release =
+        Release::fetch(&location.suite_url, &options.signature)?;
+    let repo = url.to_string();
+    let index = fetch_index(
+        &location.suite_url,
+        &release,
+        &location.index_path("Sources"),
+        &repo,
+        diagnostics,
+    )?;
+    let index = read_lossy(index)?;
+    let mut packages = Vec::new();
+    for (i, data) in deb822::parse(&index)?.iter().enumerate() {
+        match SourcePackage::parse(data, &location.archive_root)
+        {
+            Ok(package) => packages.push(package),
+            Err(e) => diagnostics.push(
+                Diagnostic::from_error(&repo, &e)
+                    .at(i + 1, data.get("Package")),
+            ),
+        }
+    }
+    Ok(packages)
+}
 
-    // Gzipを解凍
-    let compressed_data = &response_text[..];
-    let decoder = GzDecoder::new(compressed_data);
-    let reader = BufReader::new(decoder);
+/// ロケールに合った`i18n/Translation-<lang>`を、優先する順に取得します。
+/// `Release`に記載されていない言語は飛ばします。
+fn fetch_translations(
+    location: &IndexLocation,
+    release: &Release,
+    repo: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<HashMap<String, String>> {
+    let mut translations = Vec::new();
+    for language in translation::languages() {
+        let path = format!(
+            "{}/i18n/Translation-{}",
+            location.component(),
+            language
+        );
+        let result = fetch_index(
+            &location.suite_url,
+            release,
+            &path,
+            repo,
+            diagnostics,
+        )
+        .and_then(read_lossy)
+        .and_then(|content| translation::parse(&content));
+        match result {
+            Ok(translation) => translations.push(translation),
+            Err(e)
+                if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => diagnostics.push(Diagnostic::new(
+                repo,
+                format!("Failed to load {}: {}", path, e),
+            )),
+        }
+    }
+    translations
+}
 
-    // パッケージデータを格納するベクター
+/// `Packages`インデックスの内容を解析し、各パッケージとその`Description-md5`を返します。
+///
+/// 段落は`index`を借用したまま変換するため、段落やフィールドごとの文字列は確保しません。
+/// 変換できない段落は飛ばし、その理由を`repo`の問題として`diagnostics`に加えます。
+pub fn parse_packages<'a>(
+    index: &'a str,
+    repo: &str,
+    archive_root: &URL,
+    last_modified: DateTime<Local>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<(PackageMetaData, Option<&'a str>)> {
     let mut packages = Vec::new();
-    let mut current_control = String::new();
-
-    // Packagesファイルは複数のcontrolエントリが空行で区切られている
-    for line in reader.lines() {
-        let line = line?;
-        if line.trim().is_empty() {
-            // 空行の場合、現在のcontrolデータを解析
-            if !current_control.is_empty() {
-                match parse_control_file(&current_control) {
-                    Ok(parsed_data_map) => {
-                        match to_package_data(parsed_data_map) {
-                            Ok(package_data) => {
-                                let package_url_str =
-                                    get_filename(
-                                        &current_control,
-                                    );
-                                // urlのpathの最初のディレクトリを取得
-                                let parent_path = url
-                                    .path()
-                                    .to_str()
-                                    .unwrap()
-                                    .to_owned();
-                                let parent_path = parent_path
-                                    .trim_start_matches('/')
-                                    .split('/')
-                                    .next()
-                                    .unwrap_or("");
-                                let package_url = url.clone()
-                                    .join(format!("/{}/{}",parent_path,package_url_str).as_str())
-                                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-                                packages.push(PackageMetaData {
-                                    last_modified: Local::now(), // 実際の値は取得できないためデフォルト
-                                    info: package_data,
-                                    url: package_url.to_string(),
-                                });
-                            }
-                            Err(e) => eprintln!(
-                                "Failed to convert HashMap to PackageData: {}",
-                                e
-                            ),
-                        }
-                    }
-                    Err(e) => eprintln!(
-                        "Failed to parse control block into HashMap: {}",
-                        e
-                    ),
-                }
-                current_control.clear();
+    for (i, stanza) in deb822::stanzas(index).enumerate() {
+        let stanza = match stanza {
+            Ok(stanza) => stanza,
+            Err(e) => {
+                // 段落の区切りが分からなくなるため、以降は読まない
+                diagnostics.push(
+                    Diagnostic::new(repo, e).at(i + 1, None),
+                );
+                break;
             }
-        } else {
-            // 行をcurrent_controlに追加
-            current_control.push_str(&line);
-            current_control.push('\n');
+        };
+        packages.extend(package_from_stanza(
+            &stanza,
+            i + 1,
+            repo,
+            archive_root,
+            last_modified,
+            diagnostics,
+        ));
+    }
+    packages
+}
+
+/// [`parse_packages`]と同じく`Packages`インデックスを解析しますが、
+/// `reader`から1段落ずつ読み込むため、インデックス全体をメモリに保持しません。
+///
+/// 段落の区切りは空行で分かるため、解析できない段落があっても続きを読みます。
+pub fn read_packages<R: BufRead>(
+    mut reader: R,
+    repo: &str,
+    archive_root: &URL,
+    last_modified: DateTime<Local>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<(PackageMetaData, Option<String>)>, std::io::Error>
+{
+    let mut packages = Vec::new();
+    let mut buf = String::new();
+    let mut number = 0;
+    while deb822::read_stanza(&mut reader, &mut buf)? {
+        // コメントだけの段落は数えない
+        let Some(stanza) = deb822::stanzas(&buf).next() else {
+            continue;
+        };
+        number += 1;
+        let stanza = match stanza {
+            Ok(stanza) => stanza,
+            Err(e) => {
+                diagnostics.push(
+                    Diagnostic::new(repo, e).at(number, None),
+                );
+                continue;
+            }
+        };
+        packages.extend(
+            package_from_stanza(
+                &stanza,
+                number,
+                repo,
+                archive_root,
+                last_modified,
+                diagnostics,
+            )
+            .map(|(package, md5)| {
+                (package, md5.map(str::to_string))
+            }),
+        );
+    }
+    Ok(packages)
+}
+
+/// 1つの段落を変換します。変換できなければ`diagnostics`に加えて`None`を返します。
+fn package_from_stanza<'a>(
+    stanza: &Stanza<'a>,
+    number: usize,
+    repo: &str,
+    archive_root: &URL,
+    last_modified: DateTime<Local>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<(PackageMetaData, Option<&'a str>)> {
+    let at = |diagnostic: Diagnostic| {
+        diagnostic.at(number, stanza.get_raw("Package"))
+    };
+    match to_package_metadata(
+        stanza,
+        archive_root,
+        last_modified,
+    ) {
+        Ok((package, extra_error)) => {
+            // Breaksなどを解析できなくても、パッケージ自体は使える
+            if let Some(e) = extra_error {
+                diagnostics
+                    .push(at(Diagnostic::from_error(repo, &e)));
+            }
+            Some((package, stanza.get_raw("Description-md5")))
+        }
+        Err(e) => {
+            diagnostics
+                .push(at(Diagnostic::from_error(repo, &e)));
+            None
+        }
+    }
+}
+
+/// `Packages`の1つの段落からPackageMetaDataを生成します。
+///
+/// Pre-Depends、Breaks、Replaces、Enhancesを解析できなかった場合は、
+/// それらを空にしたうえで、そのエラーも返します。
+fn to_package_metadata(
+    stanza: &Stanza,
+    archive_root: &URL,
+    last_modified: DateTime<Local>,
+) -> Result<(PackageMetaData, Option<anyhow::Error>)> {
+    let info = to_package_data(stanza)?;
+    // Filenameはアーカイブのルートからの相対パス
+    let filename =
+        stanza.get_raw("Filename").unwrap_or_default();
+    let url = in_field(
+        "Filename",
+        archive_root
+            .clone()
+            .join(filename.trim_start_matches("./"))
+            .map_err(anyhow::Error::from),
+    )?;
+    let (extra_relation, extra_error) =
+        match to_extra_relation_data(stanza) {
+            Ok(extra) => (extra, None),
+            Err(e) => (ExtraRelationData::default(), Some(e)),
+        };
+    // Pre-Dependsはインストール前に満たす必要があるため、先頭に加える
+    let mut debian_depends = Vec::new();
+    for field in ["Pre-Depends", "Depends"] {
+        if let Some(value) = stanza.field(field) {
+            debian_depends.append(&mut in_field(
+                field,
+                parse_dependencies(&value),
+            )?);
         }
     }
+    let package = PackageMetaData {
+        last_modified,
+        info,
+        debian_depends,
+        url: url.to_string(),
+        extra_relation,
+        debian_version: stanza
+            .get_raw("Version")
+            .and_then(|v| v.parse().ok()),
+        multi_arch: stanza
+            .get_raw("Multi-Arch")
+            .and_then(|v| v.parse().ok()),
+        details: to_package_details(stanza),
+    };
+    Ok((package, extra_error))
+}
+
+/// 指定されたURLからPackagesファイルをダウンロードし、解析してRepoDataを返します。
+///
+/// スイートの`InRelease`/`Release`からリポジトリの情報を取得し、
+/// 署名を検証できない場合や、`Valid-Until`を過ぎている場合はエラーを返します。
+pub fn fetch(
+    url: URL,
+    options: &RepoOptions,
+) -> Result<RepoData, std::io::Error> {
+    let location = IndexLocation::new(
+        &url,
+        options.archive_root.as_deref(),
+        options.flat,
+    )?;
+    let release =
+        Release::fetch(&location.suite_url, &options.signature)?;
+    let last_modified = release.date.unwrap_or_else(Local::now);
+
+    let repo = url.to_string();
+    let mut diagnostics = Vec::new();
+    let index = fetch_index(
+        &location.suite_url,
+        &release,
+        &location.index_path("Packages"),
+        &repo,
+        &mut diagnostics,
+    )?;
+    let parsed = read_packages(
+        BufReader::new(index),
+        &repo,
+        &location.archive_root,
+        last_modified,
+        &mut diagnostics,
+    )?;
+
+    // 詳細な説明文は`i18n/Translation-<lang>`に分けられていることがある
+    let translations =
+        if parsed.iter().any(|(_, md5)| md5.is_some()) {
+            fetch_translations(
+                &location,
+                &release,
+                &repo,
+                &mut diagnostics,
+            )
+        } else {
+            vec![]
+        };
+    let packages = parsed
+        .into_iter()
+        .map(|(mut package, md5)| {
+            if let Some(description) = md5.and_then(|md5| {
+                translations.iter().find_map(|t| t.get(&md5))
+            }) {
+                package.info.about.package.description =
+                    description.clone();
+            }
+            package
+        })
+        .collect();
 
     Ok(RepoData {
         author: AuthorAboutData {
-            name: "Debian Repository".to_string(),
-            email: "debian@debian.org".to_string(),
+            name: if release.label.is_empty() {
+                release.origin.clone()
+            } else {
+                release.label.clone()
+            },
+            email: String::new(),
         },
-        last_modified: Local::now(),
+        last_modified,
         packages,
+        release: Some(release),
+        diagnostics,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extra_relations() -> Result<()> {
+        let control = "Package: libfoo\n\
+Version: 1.0-1\n\
+Architecture: amd64\n\
+Maintainer: Foo <foo@example.com>\n\
+Pre-Depends: dpkg (>= 1.17)\n\
+Depends: libc6 (>= 2.34)\n\
+Breaks: libfoo-old (<< 1.0)\n\
+Replaces: libfoo-old (<< 1.0)\n\
+Enhances: foo-plugin\n\
+Description: foo library\n";
+        let data = parse_control_file(control)?;
+        let extra = to_extra_relation_data(&data)?;
+        let package_data = to_package_data(&data)?;
+        assert_eq!(package_data.relation.depend.len(), 2);
+        assert_eq!(
+            package_data.relation.depend[0][0].name,
+            "dpkg"
+        );
+        assert_eq!(extra.breaks[0].name, "libfoo-old");
+        assert_eq!(extra.replaces[0].name, "libfoo-old");
+        assert_eq!(extra.enhances[0][0].name, "foo-plugin");
+
+        let output =
+            to_control_file(&package_data, &extra, None);
+        assert!(
+            output.contains("Pre-Depends: dpkg (>= 1.17)\n")
+        );
+        assert!(output.contains("Depends: libc6 (>= 2.34)\n"));
+        assert!(
+            output.contains("Breaks: libfoo-old (<< 1.0)\n")
+        );
+        assert!(
+            output.contains("Replaces: libfoo-old (<< 1.0)\n")
+        );
+        assert!(output.contains("Enhances: foo-plugin\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_arch_qualified_relations() -> Result<()> {
+        let host = arch::host();
+        let depends = format!(
+            "libfoo:any (>= 1), python3:native, \
+             bar [{host}] | baz [!{host}], qux [!{host}], \
+             test-tool <!nocheck>, stage-tool <stage1>"
+        );
+        let groups = parse_package_ranges(&depends)?;
+        let names: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|g| g.iter().map(|r| r.name.as_str()).collect())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                vec!["libfoo"],
+                vec!["python3"],
+                vec!["bar"],
+                vec!["test-tool"],
+            ]
+        );
+        assert!(arch::matches("linux-any", &host));
+        assert!(!arch::matches("hurd-any", &host));
+        assert_eq!(
+            "foreign".parse::<arch::MultiArch>(),
+            Ok(arch::MultiArch::Foreign)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_package_details() -> Result<()> {
+        let repo =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tmp/apt-repo");
+        let index = std::fs::read_to_string(
+            repo.join("dists/stable/main/binary-amd64/Packages"),
+        )?;
+        let details =
+            to_package_details(&parse_control_file(&index)?);
+        assert_eq!(details.size, Some(25922));
+        assert_eq!(details.installed_size, Some(104));
+        assert_eq!(
+            details.priority.as_deref(),
+            Some("optional")
+        );
+        assert_eq!(
+            details.homepage.as_deref(),
+            Some("https://www.gnu.org/software/hello/")
+        );
+        assert!(details.in_section("devel"));
+        assert!(!details.in_section("utils"));
+
+        let deb = std::fs::read(repo.join(
+            "pool/main/h/hello/hello_2.10-3build2_amd64.deb",
+        ))?;
+        assert_eq!(details.size, Some(deb.len() as u64));
+        assert_eq!(
+            details.sha256,
+            Some(crate::utils::hash::sha256_hex(&deb))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_location() -> Result<(), std::io::Error> {
+        let url = "https://mirror.example/debian/ubuntu/dists/noble/main/binary-amd64/"
+            .to_url()
+            .unwrap();
+        let location = IndexLocation::new(&url, None, false)?;
+        assert_eq!(
+            location
+                .archive_root
+                .join("pool/main/h/hello/hello_2.10-3build2_amd64.deb")?
+                .to_string(),
+            "https://mirror.example/debian/ubuntu/pool/main/h/hello/hello_2.10-3build2_amd64.deb"
+        );
+        assert_eq!(
+            location.suite_url.to_string(),
+            "https://mirror.example/debian/ubuntu/dists/noble"
+        );
+        assert_eq!(location.index_dir, "main/binary-amd64");
+
+        // dists/がない場合はアーカイブのルートの指定が必要
+        let url =
+            "https://mirror.example/noble/main/binary-amd64/"
+                .to_url()
+                .unwrap();
+        assert!(IndexLocation::new(&url, None, false).is_err());
+        let location = IndexLocation::new(
+            &url,
+            Some("https://mirror.example/archive"),
+            false,
+        )?;
+        assert_eq!(
+            location.archive_root.to_string(),
+            "https://mirror.example/archive"
+        );
+
+        // フラットなリポジトリ
+        let url =
+            "https://vendor.example/apt/".to_url().unwrap();
+        let location = IndexLocation::new(&url, None, true)?;
+        assert_eq!(location.index_path("Packages"), "Packages");
+        assert_eq!(
+            location.suite_url.to_string(),
+            "https://vendor.example/apt"
+        );
+        assert_eq!(
+            location
+                .archive_root
+                .join("hello_2.10-3build2_amd64.deb")?
+                .to_string(),
+            "https://vendor.example/apt/hello_2.10-3build2_amd64.deb"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_diagnostics() -> Result<()> {
+        let index = "Package: good
+Version: 1.0-1
+Filename: pool/main/g/good/good_1.0-1_amd64.deb
+
+Package: bad-version
+Version: :1.0
+Filename: pool/main/b/bad/bad_1.0_amd64.deb
+
+Package: bad-breaks
+Version: 2.0
+Breaks: foo (<< )
+Filename: pool/main/b/bad-breaks/bad-breaks_2.0_amd64.deb
+";
+        let root = "http://deb.example/debian".to_url().unwrap();
+        let mut diagnostics = Vec::new();
+        let packages = parse_packages(
+            index,
+            "test",
+            &root,
+            Local::now(),
+            &mut diagnostics,
+        );
+        // Breaksを解析できなくてもパッケージは残る
+        let names: Vec<&str> = packages
+            .iter()
+            .map(|(p, _)| p.info.about.package.name.as_str())
+            .collect();
+        assert_eq!(names, ["good", "bad-breaks"]);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].stanza, Some(2));
+        assert_eq!(
+            diagnostics[0].package.as_deref(),
+            Some("bad-version")
+        );
+        assert_eq!(
+            diagnostics[0].field.as_deref(),
+            Some("Version")
+        );
+        assert_eq!(
+            diagnostics[1].field.as_deref(),
+            Some("Breaks")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsatisfied_depends() -> Result<()> {
+        let index = "Package: app
+Version: 1.0
+Pre-Depends: dpkg (>= 1.19~)
+Depends: libfoo (>= 2.0~rc1), mail-transport-agent, libbar (<< 1.0) | libbar2
+Filename: pool/main/a/app/app_1.0_amd64.deb
+
+Package: libfoo
+Version: 2.0~rc1-1
+Filename: pool/main/libf/libfoo/libfoo_2.0~rc1-1_amd64.deb
+
+Package: dpkg
+Version: 1.19.0
+Filename: pool/main/d/dpkg/dpkg_1.19.0_amd64.deb
+
+Package: postfix
+Version: 3.8.5-1
+Provides: mail-transport-agent
+Filename: pool/main/p/postfix/postfix_3.8.5-1_amd64.deb
+
+Package: libbar
+Version: 1.0-1
+Filename: pool/main/libb/libbar/libbar_1.0-1_amd64.deb
+";
+        let root = "http://deb.example/debian".to_url().unwrap();
+        let mut diagnostics = Vec::new();
+        let packages: Vec<PackageMetaData> = parse_packages(
+            index,
+            "test",
+            &root,
+            Local::now(),
+            &mut diagnostics,
+        )
+        .into_iter()
+        .map(|(p, _)| p)
+        .collect();
+        assert!(diagnostics.is_empty());
+        let app = &packages[0];
+        // 制約は数字だけの"2.0"などに丸めずにそのまま保持する
+        assert_eq!(
+            app.debian_depends[0][0].to_string(),
+            "dpkg (>= 1.19~)"
+        );
+        assert_eq!(
+            app.debian_depends[1][0].to_string(),
+            "libfoo (>= 2.0~rc1)"
+        );
+        // libbar 1.0-1は"<< 1.0"を満たさない
+        let unsatisfied = app.unsatisfied_depends(&packages);
+        assert_eq!(unsatisfied.len(), 1);
+        assert_eq!(unsatisfied[0][1].name, "libbar2");
+        Ok(())
+    }
+
+    /// どのリクエストにも`body`を返すHTTPサーバーを起動し、そのURLを返します。
+    fn serve(body: Vec<u8>) -> URL {
+        use std::io::Write;
+        let listener =
+            std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+        format!("http://127.0.0.1:{}/dists/stable/", port)
+            .to_url()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_download_limits() -> Result<()> {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+        // 展開すると1MiBになる小さなgzip
+        let mut encoder =
+            GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![b'\n'; 1 << 20])?;
+        let gz = encoder.finish()?;
+        let sha256 = crate::utils::hash::sha256_hex(&gz);
+        let suite_url = serve(gz.clone());
+        let release = |gz_size: usize, plain_size: usize| {
+            Release::parse(&format!(
+                "Suite: stable
+SHA256:
+ {} {} main/binary-amd64/Packages.gz
+ {} {} main/binary-amd64/Packages
+",
+                sha256,
+                gz_size,
+                "0".repeat(64),
+                plain_size
+            ))
+        };
+        let dir = std::env::temp_dir();
+        let mut out = tempfile(&dir)?;
+        let name = "main/binary-amd64/Packages.gz";
+
+        // 展開後のサイズが記載より大きければ、記載のサイズを超えた時点で止める
+        let err = download(
+            &suite_url,
+            &release(gz.len(), 1000)?,
+            name,
+            &mut out,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("larger than listed"));
+        assert!(out.metadata()?.len() <= 1001);
+
+        // 圧縮されたファイルが記載より大きい
+        let err = download(
+            &suite_url,
+            &release(gz.len() - 1, 1 << 20)?,
+            name,
+            &mut out,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("larger than listed"));
+
+        download(
+            &suite_url,
+            &release(gz.len(), 1 << 20)?,
+            name,
+            &mut out,
+        )?;
+        assert_eq!(out.metadata()?.len(), 1 << 20);
+        Ok(())
+    }
+
+    fn tempfile(dir: &std::path::Path) -> std::io::Result<File> {
+        let path = dir.join(format!(
+            "ipm-test-download-{}",
+            std::process::id()
+        ));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        std::fs::remove_file(&path)?;
+        Ok(file)
+    }
+
+    #[test]
+    fn test_read_packages() -> Result<()> {
+        let index = "Package: good
+Version: 1.0-1
+Description-md5: 0123
+
+# comment only
+
+Package: bad-version
+Version: :1.0
+
+Package: broken
+no colon
+Version: 1.0
+
+Package: after
+Version: 2.0
+";
+        let root = "http://deb.example/debian".to_url().unwrap();
+        let mut diagnostics = Vec::new();
+        let packages = read_packages(
+            index.as_bytes(),
+            "test",
+            &root,
+            Local::now(),
+            &mut diagnostics,
+        )?;
+        let names: Vec<&str> = packages
+            .iter()
+            .map(|(p, _)| p.info.about.package.name.as_str())
+            .collect();
+        // 壊れた段落があっても、続く段落を読む
+        assert_eq!(names, ["good", "after"]);
+        assert_eq!(packages[0].1.as_deref(), Some("0123"));
+        let stanzas: Vec<Option<usize>> =
+            diagnostics.iter().map(|d| d.stanza).collect();
+        assert_eq!(stanzas, [Some(2), Some(3)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use super::debian_architecture;
+use serde::{Deserialize, Serialize};
+use std::env::consts::{ARCH, OS};
+use std::fmt;
+use std::str::FromStr;
+
+/// ホストのDebianのアーキテクチャ名 (例: "amd64")
+pub fn host() -> String {
+    debian_architecture(&[ARCH.to_string()])
+}
+
+/// アーキテクチャの指定(例: "amd64", "any", "linux-any", "any-arm64")が
+/// `arch`に一致するかどうかを返します。
+pub fn matches(pattern: &str, arch: &str) -> bool {
+    let (os, cpu) = match pattern.split_once('-') {
+        Some((os, cpu)) => (os, cpu),
+        None => return pattern == "any" || pattern == arch,
+    };
+    (os == "any" || os == OS) && (cpu == "any" || cpu == arch)
+}
+
+/// 依存関係に付けられたアーキテクチャの制限(例: `[amd64 arm64]`, `[!i386]`)が
+/// ホストで有効かどうかを返します。
+pub fn restriction_applies(restriction: &str) -> bool {
+    let host = host();
+    let archs: Vec<&str> =
+        restriction.split_whitespace().collect();
+    if archs.iter().all(|a| a.starts_with('!')) {
+        // 否定のみの場合は、どれにも一致しなければ有効
+        !archs.iter().any(|a| matches(&a[1..], &host))
+    } else {
+        archs.iter().any(|a| matches(a, &host))
+    }
+}
+
+/// ビルドプロファイルの制限(例: `<!nocheck>`, `<stage1 !cross>`)が有効かどうかを返します。
+///
+/// バイナリパッケージのインストール時にはプロファイルは有効になっていないため、
+/// 否定の条件のみが満たされます。`<...>`が複数ある場合はいずれかが満たされれば有効です。
+pub fn profiles_apply(formulas: &[&str]) -> bool {
+    formulas.is_empty()
+        || formulas.iter().any(|formula| {
+            formula
+                .split_whitespace()
+                .all(|profile| profile.starts_with('!'))
+        })
+}
+
+/// パッケージの`Multi-Arch`フィールド
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiArch {
+    No,
+    Same,
+    Foreign,
+    Allowed,
+}
+
+impl FromStr for MultiArch {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "no" => Ok(Self::No),
+            "same" => Ok(Self::Same),
+            "foreign" => Ok(Self::Foreign),
+            "allowed" => Ok(Self::Allowed),
+            _ => Err(format!("Invalid Multi-Arch: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for MultiArch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::No => "no",
+            Self::Same => "same",
+            Self::Foreign => "foreign",
+            Self::Allowed => "allowed",
+        };
+        write!(f, "{}", s)
+    }
+}
@@ -0,0 +1,192 @@
+use crate::modules::repo::diagnostic::Diagnostic;
+use crate::modules::system::path;
+use crate::utils::hash::HashingReader;
+use crate::utils::www::URL;
+use std::fs::{self, File};
+use std::io::{self, Seek};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// 展開済みのインデックスのローカルキャッシュ
+///
+/// `~/.ipm/cache/apt/`に、URLの`/`を`_`に置き換えた名前で保存します。
+/// (例: `archive.ubuntu.com_ubuntu_dists_noble_main_binary-amd64_Packages`)
+pub struct IndexCache {
+    path: PathBuf,
+}
+
+impl IndexCache {
+    /// スイートのURLと、スイートのディレクトリからの相対パスで指定されたインデックスのキャッシュ
+    pub fn new(suite_url: &URL, index_path: &str) -> Self {
+        let name = format!(
+            "{}{}/{}",
+            suite_url.domain(),
+            suite_url.path().display(),
+            index_path
+        )
+        .replace('/', "_");
+        Self { path: path::local::apt_cache_path().join(name) }
+    }
+
+    pub fn open(&self) -> Option<File> {
+        File::open(&self.path).ok()
+    }
+
+    pub fn read(&self) -> Option<Vec<u8>> {
+        fs::read(&self.path).ok()
+    }
+
+    /// キャッシュの内容を読み込まずにSHA256を計算します。
+    pub fn sha256(&self) -> Option<String> {
+        let (sha256, _) =
+            HashingReader::new(self.open()?).finish().ok()?;
+        Some(sha256)
+    }
+
+    /// キャッシュを更新します。キャッシュは必須ではないため、
+    /// 失敗しても`repo`の問題として`diagnostics`に加えるのみとします。
+    pub fn write(
+        &self,
+        content: &[u8],
+        repo: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let result = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&self.path, content));
+        if let Err(e) = result {
+            diagnostics.push(self.write_error(repo, e));
+        }
+    }
+
+    /// `fill`が書き込んだ内容でキャッシュを置き換え、先頭から読めるファイルを返します。
+    ///
+    /// 書き込み中のファイルは`.partial`とし、`fill`が成功した場合のみ置き換えます。
+    /// キャッシュに書き込めない場合は、`diagnostics`に加えて一時ファイルに書き込みます。
+    pub fn store(
+        &self,
+        repo: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+        fill: impl FnOnce(&mut File) -> Result<(), io::Error>,
+    ) -> Result<File, io::Error> {
+        let partial = self.partial_path();
+        let created = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| create_rw(&partial));
+        let (mut file, partial) = match created {
+            Ok(file) => (file, Some(partial)),
+            Err(e) => {
+                diagnostics.push(self.write_error(repo, e));
+                (temporary_file()?, None)
+            }
+        };
+        if let Err(e) = fill(&mut file) {
+            if let Some(partial) = &partial {
+                let _ = fs::remove_file(partial);
+            }
+            return Err(e);
+        }
+        if let Some(partial) = &partial {
+            fs::rename(partial, &self.path)?;
+        }
+        file.rewind()?;
+        Ok(file)
+    }
+
+    /// 書き込み中のファイルのパス
+    ///
+    /// キャッシュの名前はホスト名などの`.`を含むため、`with_extension`で
+    /// 拡張子を置き換えると別のインデックスと同じ名前になります。名前の末尾に付け加えます。
+    fn partial_path(&self) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(".partial");
+        PathBuf::from(name)
+    }
+
+    fn write_error(
+        &self,
+        repo: &str,
+        e: io::Error,
+    ) -> Diagnostic {
+        Diagnostic::new(
+            repo,
+            format!(
+                "Failed to write cache {}: {}",
+                self.path.display(),
+                e
+            ),
+        )
+    }
+}
+
+fn create_rw(path: &Path) -> Result<File, io::Error> {
+    File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+/// 名前を削除した一時ファイル (閉じると消える)
+fn temporary_file() -> Result<File, io::Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "ipm-index-{}-{}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let file = create_rw(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_dir::TempDir;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_store_dotted_names() -> Result<(), io::Error> {
+        let dir = TempDir::new("index-cache")?;
+        let cache = |name: &str| IndexCache {
+            path: dir.path().join(name),
+        };
+        let packages = cache(
+            "archive.ubuntu.com_ubuntu_dists_noble_main_binary-amd64_Packages",
+        );
+        let sources = cache(
+            "archive.ubuntu.com_ubuntu_dists_noble_main_source_Sources",
+        );
+        assert_ne!(
+            packages.partial_path(),
+            sources.partial_path()
+        );
+
+        let mut diagnostics = Vec::new();
+        // 途中で失敗した書き込みは、他のインデックスのキャッシュにならない
+        let failed =
+            packages.store("test", &mut diagnostics, |file| {
+                file.write_all(b"Package: partial\n")?;
+                Err(io::Error::other("interrupted"))
+            });
+        assert!(failed.is_err());
+        let mut file =
+            sources.store("test", &mut diagnostics, |file| {
+                file.write_all(b"Package: hello\n")
+            })?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        assert_eq!(content, "Package: hello\n");
+        assert!(packages.open().is_none());
+        assert!(!packages.partial_path().exists());
+        assert!(diagnostics.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use glob::Pattern;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// `Contents-<arch>`の1行: あるパスと、それを含むパッケージ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provider {
+    /// ルートからの絶対パス (例: "/usr/bin/hello")
+    pub path: String,
+    /// パッケージ名 (例: "hello")
+    pub packages: Vec<String>,
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.packages.join(", "), self.path)
+    }
+}
+
+/// `/usr/bin/hello`や`/usr/bin/py*`のようなパスのパターンを、
+/// `Contents-<arch>`のパスと比較できる形で解析します。
+///
+/// Contentsのパスは先頭の`/`を含まないため、取り除いてから解析します。
+pub fn pattern(path: &str) -> Result<Pattern, io::Error> {
+    Pattern::new(path.trim().trim_start_matches('/')).map_err(
+        |e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid path pattern '{}': {}",
+                    path, e
+                ),
+            )
+        },
+    )
+}
+
+/// `Contents-<arch>`を1行ずつ読み、`pattern`に一致するパスを含むパッケージを探します。
+///
+/// 各行は`<path> <section>/<package>,<section>/<package>...`の形式です。
+/// 古い形式の`FILE  LOCATION`で終わる見出しは読み飛ばします。
+pub fn search<R: BufRead>(
+    mut reader: R,
+    pattern: &Pattern,
+) -> Result<Vec<Provider>, io::Error> {
+    let mut result = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        // パスはUTF-8とは限らないため、不正なバイトは置き換える
+        let line = String::from_utf8_lossy(&buf);
+        // パスには空白が含まれることがあるため、最後の空白で区切る
+        let Some((path, location)) =
+            line.trim_end().rsplit_once(char::is_whitespace)
+        else {
+            continue;
+        };
+        let path = path.trim_end();
+        if path == "FILE" && location == "LOCATION" {
+            // ここまでは見出しの説明文
+            result.clear();
+            continue;
+        }
+        if !pattern.matches(path) {
+            continue;
+        }
+        result.push(Provider {
+            path: format!("/{}", path),
+            packages: location
+                .split(',')
+                .filter_map(|entry| entry.rsplit('/').next())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_contents() -> Result<(), io::Error> {
+        let content = "\
+usr/bin/hello                                           devel/hello
+usr/bin/python3.13                                      python/python3.13-minimal
+usr/share/doc/hello/copyright                           devel/hello,universe/devel/hello-traditional
+usr/share/fonts/My Font.ttf                             fonts/fonts-mine
+";
+        let found = search(
+            content.as_bytes(),
+            &pattern("/usr/bin/hello")?,
+        )?;
+        assert_eq!(
+            found,
+            vec![Provider {
+                path: "/usr/bin/hello".to_string(),
+                packages: vec!["hello".to_string()],
+            }]
+        );
+
+        let found = search(
+            content.as_bytes(),
+            &pattern("*/copyright")?,
+        )?;
+        assert_eq!(
+            found[0].packages,
+            ["hello", "hello-traditional"]
+        );
+        assert_eq!(
+            found[0].to_string(),
+            "hello, hello-traditional: /usr/share/doc/hello/copyright"
+        );
+
+        let found = search(
+            content.as_bytes(),
+            &pattern("/usr/bin/py*")?,
+        )?;
+        assert_eq!(found[0].packages, ["python3.13-minimal"]);
+        let found = search(
+            content.as_bytes(),
+            &pattern("/usr/share/fonts/*")?,
+        )?;
+        assert_eq!(
+            found[0].path,
+            "/usr/share/fonts/My Font.ttf"
+        );
+
+        let legacy = "This file maps each file to packages.\n\
+\n\
+FILE                                                    LOCATION\n\
+usr/bin/hello                                           devel/hello\n";
+        assert_eq!(
+            search(legacy.as_bytes(), &pattern("*")?)?.len(),
+            1
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use super::parse_control_file;
+use crate::utils::hash;
+use anyhow::{Result, anyhow};
+use std::io;
+
+/// `Packages.diff/Index`: インデックスの差分(pdiff)の一覧
+pub struct DiffIndex {
+    /// 最新のインデックスのSHA256とサイズ
+    pub current: DiffFile,
+    /// 過去のインデックス。`history[i]`は`patches[i]`を適用する前の状態です。
+    pub history: Vec<DiffFile>,
+    /// 展開した差分のSHA256とサイズ
+    pub patches: Vec<DiffFile>,
+    /// ダウンロードする圧縮された差分(`<name>.gz`)のSHA256とサイズ
+    pub downloads: Vec<DiffFile>,
+    /// `X-Patch-Precedence: merged`の場合、各差分はその状態から最新までをまとめたものです。
+    pub merged: bool,
+}
+
+/// `Packages.diff/Index`に記載された1つのファイル
+#[derive(Clone)]
+pub struct DiffFile {
+    pub sha256: String,
+    pub size: u64,
+    /// 差分の名前 (`SHA256-Current`では空)
+    pub name: String,
+}
+
+impl DiffFile {
+    /// `content`のサイズとSHA256が一致するか確認します。
+    pub fn verify(
+        &self,
+        content: &[u8],
+    ) -> Result<(), io::Error> {
+        if content.len() as u64 != self.size
+            || hash::sha256_hex(content) != self.sha256
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Checksum mismatch for pdiff {}",
+                    self.name
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl DiffIndex {
+    pub fn parse(content: &str) -> Result<Self> {
+        let data = parse_control_file(content)?;
+        let current =
+            data.get("SHA256-Current").ok_or_else(|| {
+                anyhow!("'SHA256-Current' not found")
+            })?;
+        let mut current = parse_files(current)?;
+        let files = |key: &str| {
+            parse_files(data.get(key).unwrap_or_default())
+        };
+        Ok(Self {
+            current: current.pop().ok_or_else(|| {
+                anyhow!("Empty 'SHA256-Current'")
+            })?,
+            history: files("SHA256-History")?,
+            patches: files("SHA256-Patches")?,
+            downloads: files("SHA256-Download")?,
+            merged: data
+                .get("X-Patch-Precedence")
+                .is_some_and(|v| v == "merged"),
+        })
+    }
+
+    /// SHA256が`sha256`のインデックスを最新にするために適用する差分を、適用する順に返します。
+    /// 最新であれば空、差分で更新できなければ`None`を返します。
+    pub fn patches_from(
+        &self,
+        sha256: &str,
+    ) -> Option<Vec<&DiffFile>> {
+        if self.current.sha256 == sha256 {
+            return Some(vec![]);
+        }
+        let start = self
+            .history
+            .iter()
+            .position(|file| file.sha256 == sha256)?;
+        let names = &self.history[start..];
+        let names =
+            if self.merged { &names[..1] } else { names };
+        names
+            .iter()
+            .map(|history| {
+                self.patches
+                    .iter()
+                    .find(|patch| patch.name == history.name)
+            })
+            .collect()
+    }
+
+    /// 差分`patch`のダウンロードするファイルの情報
+    pub fn download_of(
+        &self,
+        patch: &DiffFile,
+    ) -> Option<&DiffFile> {
+        let name = format!("{}.gz", patch.name);
+        self.downloads.iter().find(|file| file.name == name)
+    }
+}
+
+/// " <sha256> <size> [<name>]"の行を解析します。
+fn parse_files(value: &str) -> Result<Vec<DiffFile>> {
+    value
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            let (Some(sha256), Some(size)) =
+                (parts.next(), parts.next())
+            else {
+                return Err(anyhow!(
+                    "Malformed pdiff entry: '{}'",
+                    line
+                ));
+            };
+            Ok(DiffFile {
+                sha256: sha256.to_string(),
+                size: size.parse()?,
+                name: parts
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+/// `diff --ed`形式の差分を`base`に適用します。
+///
+/// 差分のコマンドは後ろの行から順に並んでいるため、先頭から順に適用できます。
+pub fn apply_ed(
+    base: &[u8],
+    patch: &str,
+) -> Result<Vec<u8>, io::Error> {
+    let error = |message: String| {
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    };
+    let base = std::str::from_utf8(base)
+        .map_err(|e| error(e.to_string()))?;
+    let mut lines: Vec<String> =
+        base.lines().map(str::to_string).collect();
+    let mut commands = patch.lines();
+    // 直前に追加した最後の行 (`s/.//`の対象)
+    let mut current: usize = 0;
+    while let Some(command) = commands.next() {
+        if command.is_empty() || command == "w" || command == "q"
+        {
+            continue;
+        }
+        if command == "s/.//" {
+            // "."だけの行は".."として追加され、この置換で戻される
+            if let Some(line) = current
+                .checked_sub(1)
+                .and_then(|index| lines.get_mut(index))
+                && line.starts_with('.')
+            {
+                line.remove(0);
+            }
+            continue;
+        }
+        let action = command.chars().last().unwrap_or_default();
+        let range =
+            &command[..command.len() - action.len_utf8()];
+        let (start, end) = match range.split_once(',') {
+            Some((start, end)) => (start, end),
+            None => (range, range),
+        };
+        // 行番号がなければ現在の行 (`s/.//`の後の"a"など)
+        let parse = |n: &str| {
+            if n.is_empty() {
+                return Ok(current);
+            }
+            n.parse::<usize>().map_err(|_| {
+                error(format!(
+                    "Invalid ed command '{}'",
+                    command
+                ))
+            })
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if end < start
+            || end > lines.len()
+            || (start == 0 && action != 'a')
+        {
+            return Err(error(format!(
+                "ed command '{}' is out of range",
+                command
+            )));
+        }
+        let mut text = Vec::new();
+        if action == 'a' || action == 'c' {
+            for line in commands.by_ref() {
+                if line == "." {
+                    break;
+                }
+                text.push(line.to_string());
+            }
+        }
+        let inserted = text.len();
+        match action {
+            'a' => {
+                lines.splice(start..start, text);
+                current = start + inserted;
+            }
+            'c' => {
+                lines.splice(start - 1..end, text);
+                current = start - 1 + inserted;
+            }
+            'd' => {
+                lines.drain(start - 1..end);
+                current = start - 1;
+            }
+            _ => {
+                return Err(error(format!(
+                    "Unsupported ed command '{}'",
+                    command
+                )));
+            }
+        }
+    }
+    let mut result = lines.join("\n");
+    if !lines.is_empty() {
+        result.push('\n');
+    }
+    Ok(result.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_pdiff() -> Result<()> {
+        let old = b"Package: a\nVersion: 1\n\nPackage: b\nVersion: 1\n\nPackage: c\nVersion: 1\n";
+        // `diff --ed old new`の出力
+        let patch = "7,8d\n5c\nVersion: 2\n.\n2a\nSize: 10\n.\n";
+        let new = apply_ed(old, patch)?;
+        assert_eq!(
+            String::from_utf8(new.clone())?,
+            "Package: a\nVersion: 1\nSize: 10\n\nPackage: b\nVersion: 2\n\n"
+        );
+
+        let index = DiffIndex::parse(&format!(
+            "SHA256-Current: {} {}
+SHA256-History:
+ {} {} T-1
+SHA256-Patches:
+ {} {} T-1
+SHA256-Download:
+ 0000 20 T-1.gz
+",
+            hash::sha256_hex(&new),
+            new.len(),
+            hash::sha256_hex(old),
+            old.len(),
+            hash::sha256_hex(patch.as_bytes()),
+            patch.len(),
+        ))?;
+        let patches =
+            index.patches_from(&hash::sha256_hex(old)).unwrap();
+        assert_eq!(patches.len(), 1);
+        patches[0].verify(patch.as_bytes())?;
+        assert_eq!(
+            index.download_of(patches[0]).unwrap().size,
+            20
+        );
+        assert!(
+            index
+                .patches_from(&hash::sha256_hex(&new))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(index.patches_from("unknown").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_pdiff_dot_lines() -> Result<()> {
+        let old = b"Package: a\nDescription: x\n\nPackage: b\n";
+        // "."だけの行を含む`diff --ed old new`の出力
+        let patch = "4a\n..\n.\ns/.//\n2a\n .\n..\n.\ns/.//\na\nmid\n.\n";
+        assert_eq!(
+            String::from_utf8(apply_ed(old, patch)?)?,
+            "Package: a\nDescription: x\n .\n.\nmid\n\nPackage: b\n.\n"
+        );
+        // 空の行に対する置換でpanicしない
+        assert_eq!(
+            apply_ed(b"a\n", "1a\n\n.\ns/.//\n")?,
+            b"a\n\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,381 @@
+use super::parse_control_file;
+use super::signature::{self, SignaturePolicy};
+use crate::utils::hash;
+use crate::utils::www::URL;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local};
+use ipak::utils::color::colorize::*;
+use serde::{Deserialize, Serialize};
+use std::{fmt, io};
+
+/// スイートの`Release`/`InRelease`ファイルから得られるリポジトリの情報
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Release {
+    #[serde(default)]
+    pub origin: String,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub suite: String,
+    #[serde(default)]
+    pub codename: String,
+    #[serde(default)]
+    pub date: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub valid_until: Option<DateTime<Local>>,
+    /// `Acquire-By-Hash: yes`の場合、インデックスを`by-hash/SHA256/<hash>`からも取得できます。
+    #[serde(default)]
+    pub acquire_by_hash: bool,
+    /// `SHA256`に記載されたインデックスファイルの一覧
+    #[serde(skip)]
+    pub files: Vec<IndexFile>,
+}
+
+/// `Release`の`SHA256`に記載された1つのファイル
+#[derive(Clone)]
+pub struct IndexFile {
+    /// スイートのディレクトリからの相対パス (例: "main/binary-amd64/Packages.gz")
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Release {
+    /// `Release`ファイルの内容を解析します。
+    /// `InRelease`のように署名されている場合は、署名を取り除いてから解析します。
+    pub fn parse(content: &str) -> Result<Self> {
+        let content = strip_signature(content)?;
+        let data = parse_control_file(&content)?;
+        let field = |key: &str| {
+            data.get(key).unwrap_or_default().to_string()
+        };
+        let date =
+            |key: &str| -> Result<Option<DateTime<Local>>> {
+                data.get(key).map(parse_date).transpose()
+            };
+        Ok(Self {
+            origin: field("Origin"),
+            label: field("Label"),
+            suite: field("Suite"),
+            codename: field("Codename"),
+            date: date("Date")?,
+            valid_until: date("Valid-Until")?,
+            acquire_by_hash: data
+                .get("Acquire-By-Hash")
+                .is_some_and(|v| v == "yes"),
+            files: parse_files(
+                data.get("SHA256").unwrap_or_default(),
+            )?,
+        })
+    }
+
+    /// `SHA256`に記載された`path`(スイートのディレクトリからの相対パス)のファイル
+    pub fn file(&self, path: &str) -> Option<&IndexFile> {
+        self.files.iter().find(|file| file.path == path)
+    }
+
+    /// `path`のファイルの内容が、`SHA256`に記載されたサイズとハッシュ値に
+    /// 一致するか確認します。記載されていないファイルはエラーとします。
+    pub fn verify(
+        &self,
+        path: &str,
+        content: &[u8],
+    ) -> Result<(), io::Error> {
+        self.verify_digest(
+            path,
+            &hash::sha256_hex(content),
+            content.len() as u64,
+        )
+    }
+
+    /// ダウンロードしながら求めたSHA256とサイズを、`SHA256`に記載されたものと比較します。
+    pub fn verify_digest(
+        &self,
+        path: &str,
+        sha256: &str,
+        size: u64,
+    ) -> Result<(), io::Error> {
+        let file = self.file(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not listed in Release", path),
+            )
+        })?;
+        if size != file.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Size mismatch for {}: expected {}, got {}",
+                    path, file.size, size
+                ),
+            ));
+        }
+        if sha256 != file.sha256 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("SHA256 mismatch for {}", path),
+            ));
+        }
+        Ok(())
+    }
+
+    /// `Valid-Until`が過ぎているかどうかを返します。
+    pub fn is_expired(&self, now: DateTime<Local>) -> bool {
+        self.valid_until.is_some_and(|until| until < now)
+    }
+
+    /// スイートのURL(例: `.../dists/noble/`)から`InRelease`を取得し、
+    /// 取得できなければ`Release`と`Release.gpg`を取得します。
+    ///
+    /// `policy`で信頼済みとされていない限り、署名を検証します。
+    pub fn fetch(
+        suite_url: &URL,
+        policy: &SignaturePolicy,
+    ) -> Result<Self, io::Error> {
+        // 存在しないファイル(4xx)のみNoneとし、
+        // 通信やTLSのエラーはそのまま返します。
+        let fetch =
+            |name: &str| -> Result<Option<Vec<u8>>, io::Error> {
+                let url = suite_url.clone().join(name)?;
+                match url.fetch_bin() {
+                    Ok(content) => Ok(Some(content)),
+                    Err(e)
+                        if e.downcast_ref::<reqwest::Error>(
+                        )
+                        .and_then(reqwest::Error::status)
+                        .is_some_and(|s| {
+                            s.is_client_error()
+                        }) =>
+                    {
+                        Ok(None)
+                    }
+                    Err(e) => Err(io::Error::other(format!(
+                        "Failed to fetch {}: {}",
+                        url, e
+                    ))),
+                }
+            };
+        let content = match fetch("InRelease")? {
+            Some(content) => {
+                if policy.trusted {
+                    content
+                } else {
+                    // 署名を取り除く処理に頼らず、gpgvが検証した本文を使う
+                    signature::verify_clearsigned(
+                        &content, policy,
+                    )
+                    .map_err(|e| signature_error(suite_url, e))?
+                }
+            }
+            None => {
+                let content =
+                    fetch("Release")?.ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!(
+                                "Release file not found in {}",
+                                suite_url
+                            ),
+                        )
+                    })?;
+                if !policy.trusted {
+                    let signature = fetch("Release.gpg")?
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "Release is not signed (Release.gpg not found)",
+                            )
+                        })
+                        .map_err(|e| signature_error(suite_url, e))?;
+                    signature::verify_detached(
+                        &content, &signature, policy,
+                    )
+                    .map_err(|e| {
+                        signature_error(suite_url, e)
+                    })?;
+                }
+                content
+            }
+        };
+        let content = String::from_utf8_lossy(&content);
+        let release = Self::parse(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                e.to_string(),
+            )
+        })?;
+        if release.is_expired(Local::now()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Release file of {} has expired (Valid-Until: {})",
+                    suite_url,
+                    release.valid_until.unwrap()
+                ),
+            ));
+        }
+        Ok(release)
+    }
+}
+
+impl fmt::Display for Release {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            ("Origin", self.origin.clone()),
+            ("Label", self.label.clone()),
+            ("Suite", self.suite.clone()),
+            ("Codename", self.codename.clone()),
+            (
+                "Valid Until",
+                self.valid_until
+                    .map(|d| d.to_string())
+                    .unwrap_or_default(),
+            ),
+        ];
+        for (key, value) in fields {
+            if !value.is_empty() {
+                writeln!(f, "{}: {}", key.bold(), value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn signature_error(suite_url: &URL, e: io::Error) -> io::Error {
+    io::Error::new(
+        e.kind(),
+        format!(
+            "Failed to verify the signature of {}: {}",
+            suite_url, e
+        ),
+    )
+}
+
+/// クリアテキスト署名(`InRelease`)から署名部分を取り除き、本文を返します。
+/// 署名されていない場合はそのまま返します。
+///
+/// 署名を検証しない場合(`trusted=yes`)でも、どのブロックを読むかが
+/// 曖昧になるため、署名されたブロックが複数あれば拒否します。
+pub fn strip_signature(content: &str) -> Result<String> {
+    if !content
+        .trim_start()
+        .starts_with(signature::SIGNED_MESSAGE_HEADER)
+    {
+        return Ok(content.to_string());
+    }
+    signature::check_single_block(content)?;
+    let mut body = String::new();
+    // "Hash: SHA512"などのヘッダーは空行で終わる
+    let lines = content
+        .lines()
+        .skip_while(|line| !line.trim().is_empty())
+        .skip(1);
+    for line in lines {
+        if line.starts_with("-----BEGIN PGP SIGNATURE-----") {
+            break;
+        }
+        // ダッシュエスケープ("- -----"など)を元に戻す
+        body.push_str(line.strip_prefix("- ").unwrap_or(line));
+        body.push('\n');
+    }
+    Ok(body)
+}
+
+/// `SHA256`の各行("<sha256> <size> <path>")を解析します。
+fn parse_files(value: &str) -> Result<Vec<IndexFile>> {
+    value
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let parts: Vec<&str> =
+                line.split_whitespace().collect();
+            let [sha256, size, path] = parts[..] else {
+                return Err(anyhow!(
+                    "Malformed SHA256 entry: '{}'",
+                    line
+                ));
+            };
+            Ok(IndexFile {
+                path: path.to_string(),
+                size: size.parse().map_err(|e| {
+                    anyhow!("Invalid size in '{}': {}", line, e)
+                })?,
+                sha256: sha256.to_ascii_lowercase(),
+            })
+        })
+        .collect()
+}
+
+/// Releaseの日付(例: "Thu, 25 Apr 2024 15:10:33 UTC")を解析します。
+fn parse_date(value: &str) -> Result<DateTime<Local>> {
+    let normalized = match value.trim().strip_suffix("UTC") {
+        Some(rest) => format!("{}+0000", rest),
+        None => value.trim().to_string(),
+    };
+    DateTime::parse_from_rfc2822(&normalized)
+        .map(|date| date.with_timezone(&Local))
+        .map_err(|e| {
+            anyhow!("Failed to parse date '{}': {}", value, e)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_in_release() -> Result<()> {
+        let content = "-----BEGIN PGP SIGNED MESSAGE-----\n\
+Hash: SHA512\n\
+\n\
+Origin: Ubuntu\n\
+Label: Ubuntu\n\
+Suite: noble\n\
+Codename: noble\n\
+Date: Thu, 25 Apr 2024 15:10:33 UTC\n\
+Valid-Until: Fri, 26 Apr 2024 15:10:33 UTC\n\
+Description: Ubuntu Noble 24.04\n\
+-----BEGIN PGP SIGNATURE-----\n\
+\n\
+iQIzBAEBCgAdFiEE\n\
+-----END PGP SIGNATURE-----\n";
+        let release = Release::parse(content)?;
+        assert_eq!(release.origin, "Ubuntu");
+        assert_eq!(release.suite, "noble");
+        assert_eq!(release.codename, "noble");
+        let date = release.date.unwrap();
+        assert_eq!(date.timestamp(), 1714057833);
+        assert!(release.is_expired(Local::now()));
+        assert!(!release.is_expired(date));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_index() -> Result<()> {
+        let repo =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tmp/apt-repo/dists/stable");
+        let release = Release::parse(&std::fs::read_to_string(
+            repo.join("InRelease"),
+        )?)?;
+        let path = "main/binary-amd64/Packages.gz";
+        let packages = std::fs::read(repo.join(path))?;
+        release.verify(path, &packages)?;
+        // 途中で切れたファイル
+        assert!(
+            release
+                .verify(path, &packages[..packages.len() - 1])
+                .is_err()
+        );
+        // 記載されていないファイル
+        assert!(
+            release
+                .verify(
+                    "main/binary-i386/Packages.gz",
+                    &packages
+                )
+                .is_err()
+        );
+        Ok(())
+    }
+}
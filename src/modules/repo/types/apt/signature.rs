@@ -0,0 +1,401 @@
+use crate::modules::system::path;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// リポジトリごとの署名検証の設定
+///
+/// `keyrings`が空の場合は、`trusted.gpg.d`に置かれた鍵を使います。
+/// `trusted`が有効な場合は署名を検証しません(APTの`trusted=yes`)。
+#[derive(Clone, Default)]
+pub struct SignaturePolicy {
+    pub keyrings: Vec<PathBuf>,
+    pub trusted: bool,
+}
+
+impl SignaturePolicy {
+    /// 検証に使う鍵束の一覧を返します。
+    fn keyrings(&self) -> Result<Vec<PathBuf>, io::Error> {
+        if !self.keyrings.is_empty() {
+            return Ok(self.keyrings.clone());
+        }
+        let mut keyrings = Vec::new();
+        for dir in [
+            path::global::trusted_keyrings_path(),
+            path::local::trusted_keyrings_path(),
+            PathBuf::from("/etc/apt/trusted.gpg.d"),
+        ] {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if matches!(
+                    path.extension().and_then(|e| e.to_str()),
+                    Some("gpg") | Some("asc")
+                ) {
+                    keyrings.push(path);
+                }
+            }
+        }
+        if keyrings.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No keyring configured to verify the repository signature",
+            ));
+        }
+        keyrings.sort();
+        Ok(keyrings)
+    }
+}
+
+/// クリアテキスト署名されたファイル(`InRelease`)を検証し、
+/// gpgvが署名を確認した本文を返します。
+///
+/// 署名されたブロックが複数ある場合や、ブロックの前後に
+/// 内容が付け加えられている場合は拒否します。
+pub fn verify_clearsigned(
+    content: &[u8],
+    policy: &SignaturePolicy,
+) -> Result<Vec<u8>, io::Error> {
+    let text = String::from_utf8_lossy(content);
+    let text = text.trim();
+    if !text.starts_with(SIGNED_MESSAGE_HEADER)
+        || !text.ends_with("-----END PGP SIGNATURE-----")
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Signature not found",
+        ));
+    }
+    check_single_block(text)?;
+    gpgv(&[("InRelease", content)], policy)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "gpgv did not output the signed content",
+        )
+    })
+}
+
+pub const SIGNED_MESSAGE_HEADER: &str =
+    "-----BEGIN PGP SIGNED MESSAGE-----";
+const SIGNATURE_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// クリアテキスト署名のブロックが1つだけであることを確認します。
+///
+/// 署名されていないブロックを正しいブロックの前に連結されると、
+/// 本文を取り出す側が検証されていない内容を読んでしまうためです。
+pub fn check_single_block(text: &str) -> Result<(), io::Error> {
+    for header in [SIGNED_MESSAGE_HEADER, SIGNATURE_HEADER] {
+        let count = text
+            .lines()
+            .filter(|line| line.trim() == header)
+            .count();
+        if count > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Multiple signed blocks found ({} x '{}')",
+                    count, header
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 分離署名(`Release.gpg`)で`Release`を検証します。
+pub fn verify_detached(
+    data: &[u8],
+    signature: &[u8],
+    policy: &SignaturePolicy,
+) -> Result<(), io::Error> {
+    gpgv(
+        &[("Release.gpg", signature), ("Release", data)],
+        policy,
+    )
+    .map(|_| ())
+}
+
+/// 一時ディレクトリにファイルを書き出し、gpgvで署名を検証します。
+///
+/// クリアテキスト署名の場合は、gpgvが`--output`に書き出した
+/// 署名済みの本文を返します。
+fn gpgv(
+    files: &[(&str, &[u8])],
+    policy: &SignaturePolicy,
+) -> Result<Option<Vec<u8>>, io::Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let work_dir = std::env::temp_dir().join(format!(
+        "ipm-gpgv-{}-{}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&work_dir)?;
+    let result = (|| -> Result<Option<Vec<u8>>, io::Error> {
+        let plaintext = work_dir.join("plaintext");
+        let mut command = Command::new("gpgv");
+        command
+            .arg("--homedir")
+            .arg(&work_dir)
+            .args(["--status-fd", "1"])
+            .arg("--output")
+            .arg(&plaintext);
+        for (index, keyring) in
+            policy.keyrings()?.iter().enumerate()
+        {
+            let keyring =
+                binary_keyring(keyring, &work_dir, index)?;
+            command.arg("--keyring").arg(keyring);
+        }
+        for (name, content) in files {
+            let file = work_dir.join(name);
+            fs::write(&file, content)?;
+            command.arg(file);
+        }
+        let output = command
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to run gpgv: {}", e),
+                )
+            })?;
+        check_status(&String::from_utf8_lossy(&output.stdout))?;
+        match fs::read(&plaintext) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    })();
+    fs::remove_dir_all(&work_dir)?;
+    result
+}
+
+/// gpgvは鍵束を絶対パスのバイナリ形式で受け取るため、
+/// ASCII形式(`.asc`)の鍵はgpg --dearmorで変換します。
+fn binary_keyring(
+    keyring: &Path,
+    work_dir: &Path,
+    index: usize,
+) -> Result<PathBuf, io::Error> {
+    if !keyring.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Keyring not found: {}", keyring.display()),
+        ));
+    }
+    let keyring = fs::canonicalize(keyring)?;
+    if keyring.extension().and_then(|e| e.to_str())
+        != Some("asc")
+    {
+        return Ok(keyring);
+    }
+    let mut child = Command::new("gpg")
+        .arg("--dearmor")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&fs::read(&keyring)?)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid keyring: {}", keyring.display()),
+        ));
+    }
+    let dearmored =
+        work_dir.join(format!("keyring-{}.gpg", index));
+    fs::write(&dearmored, output.stdout)?;
+    Ok(dearmored)
+}
+
+/// gpgvの`--status-fd`の出力から検証結果を判定します。
+///
+/// 信頼された鍵による正しい署名が必要です。正しい署名があっても、
+/// 検証できない署名や不正な署名が一緒に含まれていれば失敗とします。
+fn check_status(status: &str) -> Result<(), io::Error> {
+    let mut good = false;
+    let mut bad = Vec::new();
+    let mut unknown_keys = Vec::new();
+    let mut no_data = false;
+    for line in status.lines() {
+        let mut fields = line
+            .trim_start_matches("[GNUPG:] ")
+            .split_whitespace();
+        let Some(keyword) = fields.next() else {
+            continue;
+        };
+        let key = fields.next().unwrap_or_default().to_string();
+        match keyword {
+            "GOODSIG" => good = true,
+            "NO_PUBKEY" => unknown_keys.push(key),
+            "BADSIG" | "ERRSIG" | "EXPSIG" | "EXPKEYSIG"
+            | "REVKEYSIG" => bad.push((key, keyword)),
+            "NODATA" => no_data = true,
+            _ => {}
+        }
+    }
+    // 鍵がない場合はERRSIGとNO_PUBKEYの両方が出力される
+    let bad: Vec<String> = bad
+        .into_iter()
+        .filter(|(key, keyword)| {
+            *keyword != "ERRSIG" || !unknown_keys.contains(key)
+        })
+        .map(|(key, keyword)| format!("{} ({})", key, keyword))
+        .collect();
+    if !bad.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Bad signature made by key {}",
+                bad.join(", ")
+            ),
+        ));
+    }
+    if !unknown_keys.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "Signature made by unknown key {}",
+                unknown_keys.join(", ")
+            ),
+        ));
+    }
+    if no_data {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Signature not found",
+        ));
+    }
+    if good {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Signature could not be verified",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tmp/apt-repo")
+            .join(path)
+    }
+
+    fn policy(keyring: &str) -> SignaturePolicy {
+        SignaturePolicy {
+            keyrings: vec![fixture(keyring)],
+            trusted: false,
+        }
+    }
+
+    #[test]
+    fn test_verify_release_signature() -> Result<(), io::Error> {
+        let in_release =
+            fs::read(fixture("dists/stable/InRelease"))?;
+        let release = fs::read(fixture("dists/stable/Release"))?;
+        let signature =
+            fs::read(fixture("dists/stable/Release.gpg"))?;
+        let trusted = policy("keyring.gpg");
+        assert_eq!(
+            verify_clearsigned(&in_release, &trusted)?,
+            release
+        );
+        verify_detached(&release, &signature, &trusted)?;
+
+        // 改ざんされた内容
+        let mut modified = release.clone();
+        modified.extend_from_slice(b"Extra: field\n");
+        assert!(
+            verify_detached(&modified, &signature, &trusted)
+                .is_err()
+        );
+
+        // 署名がない
+        let err =
+            verify_clearsigned(&release, &trusted).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // 未知の鍵による署名
+        let err = verify_clearsigned(
+            &in_release,
+            &policy("unknown-keyring.gpg"),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_concatenated_blocks() -> Result<(), io::Error>
+    {
+        let in_release =
+            fs::read(fixture("dists/stable/InRelease"))?;
+        let trusted = policy("keyring.gpg");
+        // 署名されていない偽のブロックを正しいブロックの前に連結する
+        let mut forged = b"-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA256
+
+Origin: Evil
+-----BEGIN PGP SIGNATURE-----
+
+iHUEARYIAB0WIQQ=
+-----END PGP SIGNATURE-----
+"
+        .to_vec();
+        forged.extend_from_slice(&in_release);
+        let err =
+            verify_clearsigned(&forged, &trusted).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            err.to_string().contains("Multiple signed blocks")
+        );
+
+        let twice = [in_release.clone(), in_release].concat();
+        assert!(verify_clearsigned(&twice, &trusted).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_status() {
+        let good = "[GNUPG:] NEWSIG
+[GNUPG:] GOODSIG 3AFA750DCBFB40DD ipm test repository
+[GNUPG:] VALIDSIG A6918875949D7BC2F201C7F43AFA750DCBFB40DD
+";
+        assert!(check_status(good).is_ok());
+        // 検証できない署名が一緒にあれば失敗とする
+        let unknown = format!(
+            "{}[GNUPG:] ERRSIG 1234567890ABCDEF 22 8 00 1792238317 9 -
+[GNUPG:] NO_PUBKEY 1234567890ABCDEF
+",
+            good
+        );
+        assert_eq!(
+            check_status(&unknown).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        let bad = format!(
+            "{}[GNUPG:] BADSIG 1234567890ABCDEF evil\n",
+            good
+        );
+        assert_eq!(
+            check_status(&bad).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(check_status("[GNUPG:] NODATA 1\n").is_err());
+    }
+}
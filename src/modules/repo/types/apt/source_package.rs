@@ -0,0 +1,257 @@
+use super::version::DebianVersion;
+use crate::utils::deb822::Paragraph;
+use crate::utils::www::URL;
+use anyhow::{Result, anyhow};
+use ipak::utils::color::colorize::*;
+use std::{fmt, io};
+
+/// `Sources`インデックスの1つのソースパッケージ
+#[derive(Clone)]
+pub struct SourcePackage {
+    pub name: String,
+    pub version: DebianVersion,
+    /// このソースからビルドされるバイナリパッケージ
+    pub binaries: Vec<String>,
+    /// `Vcs-Git`や`Vcs-Browser`などのフィールド (フィールド名, 値)
+    pub vcs: Vec<(String, String)>,
+    /// `.dsc`、`.orig.tar.*`、`.debian.tar.*`などのファイル
+    pub files: Vec<SourceFile>,
+}
+
+/// ソースパッケージを構成する1つのファイル
+#[derive(Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub size: u64,
+    /// `Checksums-Sha256`がない古いインデックスでは空になります。
+    pub sha256: String,
+    pub url: String,
+}
+
+impl SourcePackage {
+    /// `Sources`の1つの段落を解析します。
+    /// ファイルのURLは`archive_root`と`Directory`から求めます。
+    pub fn parse(
+        data: &Paragraph,
+        archive_root: &URL,
+    ) -> Result<Self> {
+        let name = data.get("Package").ok_or_else(|| {
+            anyhow!("'Package' field not found")
+        })?;
+        let version: DebianVersion = data
+            .get("Version")
+            .ok_or_else(|| {
+                anyhow!("'Version' field not found in {}", name)
+            })?
+            .parse()?;
+        let directory =
+            data.get("Directory").unwrap_or_default();
+        // Checksums-Sha256がなければ、MD5の`Files`からファイル名とサイズだけを使う
+        let (checksums, has_sha256) = match data
+            .get("Checksums-Sha256")
+        {
+            Some(value) => (value, true),
+            None => {
+                (data.get("Files").unwrap_or_default(), false)
+            }
+        };
+        let mut files = Vec::new();
+        for line in
+            checksums.lines().filter(|l| !l.trim().is_empty())
+        {
+            let mut parts = line.split_whitespace();
+            let (Some(hash), Some(size), Some(file_name)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(anyhow!(
+                    "Malformed file entry in {}: '{}'",
+                    name,
+                    line
+                ));
+            };
+            // ファイル名はそのままカレントディレクトリへの保存に使うため、
+            // ディレクトリを含むものや"."/".."は受け付けない
+            if file_name.contains(['/', '\\'])
+                || matches!(file_name, "" | "." | "..")
+            {
+                return Err(anyhow!(
+                    "Invalid file name in {}: '{}'",
+                    name,
+                    file_name
+                ));
+            }
+            let url = archive_root
+                .clone()
+                .join(&format!("{}/{}", directory, file_name))?;
+            files.push(SourceFile {
+                name: file_name.to_string(),
+                size: size.parse()?,
+                sha256: if has_sha256 {
+                    hash.to_string()
+                } else {
+                    String::new()
+                },
+                url: url.to_string(),
+            });
+        }
+        Ok(Self {
+            name: name.to_string(),
+            version,
+            binaries: data
+                .get("Binary")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|b| !b.is_empty())
+                .map(str::to_string)
+                .collect(),
+            vcs: data
+                .fields()
+                .filter(|(key, _)| key.starts_with("Vcs-"))
+                .map(|(key, value)| {
+                    (key.to_string(), value.to_string())
+                })
+                .collect(),
+            files,
+        })
+    }
+
+    /// `.dsc`ファイル
+    pub fn dsc(&self) -> Option<&SourceFile> {
+        self.files
+            .iter()
+            .find(|file| file.name.ends_with(".dsc"))
+    }
+
+    /// 上流のソースの`.orig.tar.*`ファイル (署名の`.asc`も含みます)
+    pub fn orig(&self) -> Vec<&SourceFile> {
+        self.files
+            .iter()
+            .filter(|file| file.name.contains(".orig"))
+            .collect()
+    }
+}
+
+impl SourceFile {
+    /// ダウンロードした内容のSHA256とサイズが`Sources`の記載に一致するか確認します。
+    pub fn verify(
+        &self,
+        sha256: &str,
+        size: u64,
+    ) -> Result<(), io::Error> {
+        if size != self.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Size mismatch for {}: expected {}, got {}",
+                    self.name, self.size, size
+                ),
+            ));
+        }
+        if self.sha256.is_empty() {
+            return Ok(());
+        }
+        if sha256 != self.sha256 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("SHA256 mismatch for {}", self.name),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SourcePackage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {}", "Source".bold(), self.name)?;
+        writeln!(f, "{}: {}", "Version".bold(), self.version)?;
+        if !self.binaries.is_empty() {
+            writeln!(
+                f,
+                "{}: {}",
+                "Binary".bold(),
+                self.binaries.join(", ")
+            )?;
+        }
+        for (key, value) in &self.vcs {
+            writeln!(f, "{}: {}", key.bold(), value)?;
+        }
+        for file in &self.files {
+            writeln!(f, "  {}", file.url.cyan())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::www::ToURL;
+
+    #[test]
+    fn test_parse_source_package() -> Result<()> {
+        let data = Paragraph::parse(
+            "Package: hello
+Binary: hello, hello-dbgsym
+Version: 2.10-3build2
+Vcs-Browser: https://salsa.debian.org/debian/hello
+Vcs-Git: https://salsa.debian.org/debian/hello.git
+Directory: pool/main/h/hello
+Checksums-Sha256:
+ 0000000000000000000000000000000000000000000000000000000000000001 1847 hello_2.10-3build2.dsc
+ 0000000000000000000000000000000000000000000000000000000000000002 725946 hello_2.10.orig.tar.gz
+ 0000000000000000000000000000000000000000000000000000000000000003 12688 hello_2.10-3build2.debian.tar.xz
+",
+        )?;
+        let root =
+            "http://archive.ubuntu.com/ubuntu".to_url().unwrap();
+        let source = SourcePackage::parse(&data, &root)?;
+        assert_eq!(source.binaries, ["hello", "hello-dbgsym"]);
+        assert_eq!(source.vcs.len(), 2);
+        assert_eq!(
+            source.dsc().unwrap().url,
+            "http://archive.ubuntu.com/ubuntu/pool/main/h/hello/hello_2.10-3build2.dsc"
+        );
+        assert_eq!(source.orig().len(), 1);
+        assert_eq!(source.orig()[0].size, 725946);
+        let sha256 = &source.files[0].sha256;
+        assert!(source.files[0].verify(sha256, 1847).is_ok());
+        assert!(source.files[0].verify(sha256, 5).is_err());
+        assert!(
+            source.files[0]
+                .verify(&"0".repeat(64), 1847)
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_unsafe_file_names() {
+        let root =
+            "http://archive.ubuntu.com/ubuntu".to_url().unwrap();
+        for file_name in [
+            "../../.bashrc",
+            "/etc/passwd",
+            "sub/hello.dsc",
+            "..\\hello.dsc",
+            "..",
+            ".",
+        ] {
+            let data = Paragraph::parse(&format!(
+                "Package: hello
+Version: 2.10-3build2
+Directory: pool/main/h/hello
+Checksums-Sha256:
+ 0000000000000000000000000000000000000000000000000000000000000001 1847 {}
+",
+                file_name
+            ))
+            .unwrap();
+            assert!(
+                SourcePackage::parse(&data, &root).is_err(),
+                "{} should be rejected",
+                file_name
+            );
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use super::debian_architecture;
+use crate::modules::repo::RepoOptions;
+use crate::modules::repo::diagnostic::Diagnostic;
+use crate::utils::deb822;
+use anyhow::{Result, anyhow};
+use std::env::consts::ARCH;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// deb822形式のフィールドのうち、オプションではなくエントリの構造を表すもの
+const STRUCTURAL_FIELDS: [&str; 5] =
+    ["Types", "URIs", "Suites", "Components", "Enabled"];
+
+/// `SourceEntry`から求めたインデックスのディレクトリ
+pub struct IndexUrl {
+    /// アーカイブのルート(`<uri>`)
+    pub root: String,
+    pub url: String,
+    /// フラットなリポジトリかどうか
+    pub flat: bool,
+}
+
+/// sources.list(1行形式)またはdeb822形式(`.sources`)で記述されたAPTリポジトリ
+#[derive(Clone, Default)]
+pub struct SourceEntry {
+    /// "deb"または"deb-src"
+    pub types: Vec<String>,
+    pub uris: Vec<String>,
+    pub suites: Vec<String>,
+    pub components: Vec<String>,
+    /// 空の場合はホストのアーキテクチャを使います。
+    pub architectures: Vec<String>,
+    pub options: RepoOptions,
+}
+
+impl SourceEntry {
+    /// 1行形式のエントリ
+    /// (例: `deb [arch=amd64 signed-by=/path/key.gpg] http://archive.ubuntu.com/ubuntu plucky main universe`)
+    /// を解析します。
+    /// 無視したオプションは`diagnostics`に加えます。
+    pub fn parse_line(
+        line: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Self> {
+        let line = line.trim();
+        let (source_type, rest) =
+            line.split_once(char::is_whitespace).ok_or_else(
+                || anyhow!("Malformed source entry: '{}'", line),
+            )?;
+        let (mut entry, rest) =
+            parse_options(rest.trim(), diagnostics);
+        let mut words = rest.split_whitespace();
+        let (Some(uri), Some(suite)) =
+            (words.next(), words.next())
+        else {
+            return Err(anyhow!(
+                "Source entry must have a URI and a suite: '{}'",
+                line
+            ));
+        };
+        entry.types = vec![source_type.to_string()];
+        entry.uris = vec![uri.to_string()];
+        entry.suites = vec![suite.to_string()];
+        entry.components = words.map(str::to_string).collect();
+        Ok(entry)
+    }
+
+    /// deb822形式(`.sources`)の内容を解析します。
+    /// `Enabled: no`のエントリは含めません。
+    /// 無視したオプションは`diagnostics`に加えます。
+    pub fn parse_deb822(
+        content: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<Self>> {
+        let mut entries = Vec::new();
+        for data in deb822::parse(content)? {
+            if data
+                .get("Enabled")
+                .is_some_and(|v| v.eq_ignore_ascii_case("no"))
+            {
+                continue;
+            }
+            let list = |key: &str| -> Vec<String> {
+                data.get(key)
+                    .map(|value| {
+                        value
+                            .split_whitespace()
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            let mut entry = Self {
+                types: list("Types"),
+                uris: list("URIs"),
+                suites: list("Suites"),
+                components: list("Components"),
+                ..Self::default()
+            };
+            let repo = entry.uris.join(" ");
+            // 構造を表すフィールド以外は、1行形式のオプションと同様に扱う
+            for (key, value) in
+                data.fields().filter(|(key, _)| {
+                    !STRUCTURAL_FIELDS.iter().any(|field| {
+                        field.eq_ignore_ascii_case(key)
+                    })
+                })
+            {
+                entry.set_option(key, value, &repo, diagnostics);
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// `.sources`ファイル(deb822形式)または`.list`ファイル(1行形式)を読み込みます。
+    pub fn from_file(
+        path: &Path,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<Self>, io::Error> {
+        let content = fs::read_to_string(path)?;
+        let result = if path
+            .extension()
+            .is_some_and(|ext| ext == "sources")
+        {
+            Self::parse_deb822(&content, diagnostics)
+        } else {
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| {
+                    !line.is_empty() && !line.starts_with('#')
+                })
+                .map(|line| Self::parse_line(line, diagnostics))
+                .collect()
+        };
+        result.map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse {}: {}",
+                    path.display(),
+                    e
+                ),
+            )
+        })
+    }
+
+    /// バイナリパッケージのインデックスのあるディレクトリ
+    /// (`<uri>/dists/<suite>/<component>/binary-<arch>/`)を全て返します。
+    pub fn binary_indexes(&self) -> Vec<IndexUrl> {
+        let architectures = if self.architectures.is_empty() {
+            vec![debian_architecture(&[ARCH.to_string()])]
+        } else {
+            self.architectures.clone()
+        };
+        let dirs: Vec<String> = architectures
+            .iter()
+            .map(|arch| format!("binary-{}", arch))
+            .collect();
+        self.indexes("deb", &dirs)
+    }
+
+    /// ソースパッケージのインデックスのあるディレクトリ
+    /// (`<uri>/dists/<suite>/<component>/source/`)を全て返します。
+    pub fn source_indexes(&self) -> Vec<IndexUrl> {
+        self.indexes("deb-src", &["source".to_string()])
+    }
+
+    /// `/`で終わるスイート(例: `./`)はフラットなリポジトリとして扱い、
+    /// コンポーネントの代わりに`<uri>/<suite>`をインデックスのディレクトリとします。
+    fn indexes(
+        &self,
+        source_type: &str,
+        dirs: &[String],
+    ) -> Vec<IndexUrl> {
+        if !self.types.iter().any(|t| t == source_type) {
+            return vec![];
+        }
+        let mut indexes = Vec::new();
+        for uri in &self.uris {
+            let root = uri.trim_end_matches('/');
+            for suite in &self.suites {
+                if suite.ends_with('/') {
+                    let dir = suite
+                        .trim_start_matches("./")
+                        .trim_end_matches('/');
+                    indexes.push(IndexUrl {
+                        root: root.to_string(),
+                        url: if dir.is_empty() {
+                            format!("{}/", root)
+                        } else {
+                            format!("{}/{}/", root, dir)
+                        },
+                        flat: true,
+                    });
+                    continue;
+                }
+                for component in &self.components {
+                    for dir in dirs {
+                        indexes.push(IndexUrl {
+                            root: root.to_string(),
+                            url: format!(
+                                "{}/dists/{}/{}/{}/",
+                                root, suite, component, dir
+                            ),
+                            flat: false,
+                        });
+                    }
+                }
+            }
+        }
+        indexes
+    }
+
+    /// オプション(1行形式の`[...]`内、またはdeb822のフィールド)を設定します。
+    /// 対応していないオプションは、`repo`の問題として`diagnostics`に加えます。
+    fn set_option(
+        &mut self,
+        key: &str,
+        value: &str,
+        repo: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        match key.to_ascii_lowercase().as_str() {
+            "arch" | "architectures" => {
+                self.architectures = value
+                    .split([',', ' '])
+                    .filter(|a| !a.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "signed-by" => {
+                if value.trim_start().starts_with("-----BEGIN") {
+                    diagnostics.push(Diagnostic::new(
+                        repo,
+                        "Inline keys in Signed-By are not supported",
+                    ));
+                    return;
+                }
+                self.options.signature.keyrings.extend(
+                    value
+                        .split([',', ' ', '\n'])
+                        .filter(|k| !k.is_empty())
+                        .map(PathBuf::from),
+                )
+            }
+            "trusted" => {
+                self.options.signature.trusted = value == "yes"
+            }
+            // ipm独自のオプション: アーカイブのルートを明示する
+            "archive-root" => {
+                self.options.archive_root =
+                    Some(value.to_string())
+            }
+            // ipm独自のオプション: フラットなリポジトリであることを明示する
+            "flat" => self.options.flat = value == "yes",
+            _ => diagnostics.push(Diagnostic::new(
+                repo,
+                format!(
+                    "Unknown repository option ignored: '{}'",
+                    key
+                ),
+            )),
+        }
+    }
+}
+
+/// 先頭に置かれたAPT形式のオプション
+/// (例: `[signed-by=/path/to/key.gpg trusted=yes] https://...`)を解析し、
+/// オプションを設定したエントリと残りの文字列を返します。
+/// 無視したオプションは`diagnostics`に加えます。
+pub fn parse_options<'a>(
+    s: &'a str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> (SourceEntry, &'a str) {
+    let mut entry = SourceEntry::default();
+    let Some((options, rest)) =
+        s.strip_prefix('[').and_then(|s| s.split_once(']'))
+    else {
+        return (entry, s);
+    };
+    let rest = rest.trim();
+    // 1行形式ではオプションの後にURIが続きます
+    let repo = rest.split_whitespace().next().unwrap_or(rest);
+    for option in options.split_whitespace() {
+        match option.split_once('=') {
+            Some((key, value)) => {
+                entry.set_option(key, value, repo, diagnostics)
+            }
+            None => diagnostics.push(Diagnostic::new(
+                repo,
+                format!(
+                    "Unknown repository option ignored: '{}'",
+                    option
+                ),
+            )),
+        }
+    }
+    (entry, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sources() -> Result<()> {
+        let mut diagnostics = Vec::new();
+        let entry = SourceEntry::parse_line(
+            "deb [arch=amd64,arm64 signed-by=/usr/share/keyrings/ubuntu-archive-keyring.gpg] http://archive.ubuntu.com/ubuntu/ plucky main universe",
+            &mut diagnostics,
+        )?;
+        assert_eq!(
+            entry.options.signature.keyrings,
+            vec![PathBuf::from(
+                "/usr/share/keyrings/ubuntu-archive-keyring.gpg"
+            )]
+        );
+        let indexes = entry.binary_indexes();
+        assert_eq!(indexes.len(), 4);
+        assert_eq!(
+            indexes[0].root,
+            "http://archive.ubuntu.com/ubuntu"
+        );
+        assert_eq!(
+            indexes[0].url,
+            "http://archive.ubuntu.com/ubuntu/dists/plucky/main/binary-amd64/"
+        );
+        assert_eq!(
+            indexes[3].url,
+            "http://archive.ubuntu.com/ubuntu/dists/plucky/universe/binary-arm64/"
+        );
+
+        let entries = SourceEntry::parse_deb822(
+            "# Ubuntu
+Types: deb deb-src
+URIs: http://archive.ubuntu.com/ubuntu/
+Suites: noble noble-updates
+Components: main
+Architectures: amd64
+Signed-By: /usr/share/keyrings/ubuntu-archive-keyring.gpg
+
+Types: deb
+URIs: http://example.com/debian
+Suites: stable
+Components: main
+Enabled: no
+",
+            &mut diagnostics,
+        )?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0]
+                .binary_indexes()
+                .into_iter()
+                .map(|index| index.url)
+                .collect::<Vec<String>>(),
+            vec![
+                "http://archive.ubuntu.com/ubuntu/dists/noble/main/binary-amd64/",
+                "http://archive.ubuntu.com/ubuntu/dists/noble-updates/main/binary-amd64/",
+            ]
+        );
+        assert_eq!(
+            entries[0].source_indexes()[0].url,
+            "http://archive.ubuntu.com/ubuntu/dists/noble/main/source/"
+        );
+        assert!(entry.source_indexes().is_empty());
+
+        let entry = SourceEntry::parse_line(
+            "deb [trusted=yes] https://vendor.example/apt ./",
+            &mut diagnostics,
+        )?;
+        let indexes = entry.binary_indexes();
+        assert_eq!(indexes.len(), 1);
+        assert!(indexes[0].flat);
+        assert_eq!(
+            indexes[0].url,
+            "https://vendor.example/apt/"
+        );
+        assert_eq!(
+            indexes[0].root,
+            "https://vendor.example/apt"
+        );
+        assert!(diagnostics.is_empty());
+
+        let entry = SourceEntry::parse_line(
+            "deb [check-valid-until=no by-hash] https://vendor.example/debian stable main",
+            &mut diagnostics,
+        )?;
+        assert_eq!(entry.suites, vec!["stable"]);
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    "https://vendor.example/debian",
+                    "Unknown repository option ignored: 'check-valid-until'",
+                ),
+                Diagnostic::new(
+                    "https://vendor.example/debian",
+                    "Unknown repository option ignored: 'by-hash'",
+                ),
+            ]
+        );
+
+        let mut diagnostics = Vec::new();
+        let entries = SourceEntry::parse_deb822(
+            "Types: deb
+URIs: https://vendor.example/apt
+Suites: ./
+Archive-Root: https://vendor.example
+Flat: yes
+X-Repolib-Name: vendor
+
+Types: deb
+URIs: http://example.com/debian
+Suites: stable
+Components: main
+Enabled: No
+",
+            &mut diagnostics,
+        )?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].options.archive_root.as_deref(),
+            Some("https://vendor.example")
+        );
+        assert!(entries[0].options.flat);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                "https://vendor.example/apt",
+                "Unknown repository option ignored: 'X-Repolib-Name'",
+            )]
+        );
+        Ok(())
+    }
+}
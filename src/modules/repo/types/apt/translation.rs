@@ -0,0 +1,96 @@
+use super::description_from_field;
+use crate::utils::deb822;
+use std::collections::HashMap;
+use std::env;
+use std::io;
+
+/// 環境変数(`LC_ALL`、`LC_MESSAGES`、`LANG`の順)のロケールから、
+/// `Translation-<lang>`を探す言語の一覧を優先する順に返します。
+pub fn languages() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|key| env::var(key).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    languages_from(&locale)
+}
+
+/// ロケール(例: "ja_JP.UTF-8")から言語の一覧(例: `["ja_JP", "ja", "en"]`)を求めます。
+/// 最後は常に`en`です。
+pub fn languages_from(locale: &str) -> Vec<String> {
+    // "ja_JP.UTF-8@variant"の".UTF-8"と"@variant"は使わない
+    let locale =
+        locale.split(['.', '@']).next().unwrap_or_default();
+    let mut languages = Vec::new();
+    if !locale.is_empty() && locale != "C" && locale != "POSIX" {
+        languages.push(locale.to_string());
+        if let Some((language, _)) = locale.split_once('_') {
+            languages.push(language.to_string());
+        }
+    }
+    languages.push("en".to_string());
+    languages.dedup();
+    languages
+}
+
+/// `Translation-<lang>`を解析し、`Description-md5`から説明文への対応を返します。
+pub fn parse(
+    content: &str,
+) -> Result<HashMap<String, String>, io::Error> {
+    let mut translations = HashMap::new();
+    for data in deb822::parse(content)? {
+        let Some(md5) = data.get("Description-md5") else {
+            continue;
+        };
+        // フィールド名は"Description-en"や"Description-ja"など
+        let description = data
+            .fields()
+            .find(|(key, _)| {
+                let key = key.to_ascii_lowercase();
+                key.starts_with("description-")
+                    && key != "description-md5"
+            })
+            .map(|(_, value)| value);
+        if let Some(description) = description {
+            translations.insert(
+                md5.to_string(),
+                description_from_field(description),
+            );
+        }
+    }
+    Ok(translations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translations() -> Result<(), io::Error> {
+        assert_eq!(
+            languages_from("ja_JP.UTF-8"),
+            ["ja_JP", "ja", "en"]
+        );
+        assert_eq!(languages_from("C.UTF-8"), ["en"]);
+        assert_eq!(languages_from("en_US"), ["en_US", "en"]);
+        assert_eq!(languages_from(""), ["en"]);
+
+        let translations = parse(
+            "Package: hello
+Description-md5: b7a6e1e8dbd8a4a2c5e9a2ab86ec8ad3
+Description-ja: GNU hello をベースにしたパッケージの例
+ GNU hello プログラムは、親しみやすい挨拶を出力します。
+ .
+ これは Debian パッケージの作り方の例です。
+",
+        )?;
+        assert_eq!(
+            translations["b7a6e1e8dbd8a4a2c5e9a2ab86ec8ad3"],
+            "GNU hello をベースにしたパッケージの例\n\
+GNU hello プログラムは、親しみやすい挨拶を出力します。\n\
+\n\
+これは Debian パッケージの作り方の例です。"
+        );
+        Ok(())
+    }
+}
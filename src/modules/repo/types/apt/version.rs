@@ -0,0 +1,376 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Debianのバージョン(`[epoch:]upstream_version[-debian_revision]`)
+///
+/// 比較はdpkgと同じ規則で行います。
+/// (例: `1.0~rc1` < `1.0` < `1.0-1` < `1.0a` < `1.0+b1` < `1:0.1`)
+#[derive(Debug, Clone)]
+pub struct DebianVersion {
+    pub epoch: u64,
+    pub upstream: String,
+    pub revision: String,
+}
+
+impl FromStr for DebianVersion {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(anyhow!(
+                "Invalid Debian version '{}'",
+                s
+            ));
+        }
+        let (epoch, rest) = match s.split_once(':') {
+            Some((epoch, rest)) => (
+                epoch.parse().map_err(|_| {
+                    anyhow!("Invalid epoch in version '{}'", s)
+                })?,
+                rest,
+            ),
+            None => (0, s),
+        };
+        let (upstream, revision) = match rest.rsplit_once('-') {
+            Some((upstream, revision)) => (upstream, revision),
+            None => (rest, ""),
+        };
+        if upstream.is_empty() {
+            return Err(anyhow!(
+                "Empty upstream version in '{}'",
+                s
+            ));
+        }
+        Ok(Self {
+            epoch,
+            upstream: upstream.to_string(),
+            revision: revision.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for DebianVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.epoch > 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+        write!(f, "{}", self.upstream)?;
+        if !self.revision.is_empty() {
+            write!(f, "-{}", self.revision)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for DebianVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| {
+                compare_part(&self.upstream, &other.upstream)
+            })
+            .then_with(|| {
+                compare_part(&self.revision, &other.revision)
+            })
+    }
+}
+
+impl PartialOrd for DebianVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for DebianVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DebianVersion {}
+
+impl Serialize for DebianVersion {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for DebianVersion {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// dpkgの文字の順序: `~`は空よりも前、英字はそれ以外の記号よりも前
+fn order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => c as i32,
+        Some(b'~') => -1,
+        Some(c) => c as i32 + 256,
+    }
+}
+
+/// upstream_version/debian_revisionを、数字以外の部分と数字の部分を
+/// 交互に比較します(dpkgの`verrevcmp`)。
+fn compare_part(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !a[i].is_ascii_digit())
+            || (j < b.len() && !b[j].is_ascii_digit())
+        {
+            let ac = order(a.get(i).copied());
+            let bc = order(b.get(j).copied());
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+        while i < a.len() && a[i] == b'0' {
+            i += 1;
+        }
+        while j < b.len() && b[j] == b'0' {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while i < a.len()
+            && a[i].is_ascii_digit()
+            && j < b.len()
+            && b[j].is_ascii_digit()
+        {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if i < a.len() && a[i].is_ascii_digit() {
+            return Ordering::Greater;
+        }
+        if j < b.len() && b[j].is_ascii_digit() {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
+
+/// 依存関係のバージョンの関係演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    /// `<<`
+    Earlier,
+    /// `<=`
+    EarlierOrEqual,
+    /// `=`
+    Exactly,
+    /// `>=`
+    LaterOrEqual,
+    /// `>>`
+    Later,
+}
+
+impl FromStr for Relation {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "<<" => Ok(Self::Earlier),
+            // 古い形式の"<"と">"は"<="と">="を意味する
+            "<=" | "<" => Ok(Self::EarlierOrEqual),
+            "=" => Ok(Self::Exactly),
+            ">=" | ">" => Ok(Self::LaterOrEqual),
+            ">>" => Ok(Self::Later),
+            _ => Err(anyhow!("Invalid relation '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Self::Earlier => "<<",
+            Self::EarlierOrEqual => "<=",
+            Self::Exactly => "=",
+            Self::LaterOrEqual => ">=",
+            Self::Later => ">>",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// `(>= 2.38)`のようなバージョンの制約
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionConstraint {
+    pub relation: Relation,
+    pub version: DebianVersion,
+}
+
+impl VersionConstraint {
+    /// `version`がこの制約を満たすかどうかを返します。
+    pub fn satisfied_by(&self, version: &DebianVersion) -> bool {
+        let ordering = version.cmp(&self.version);
+        match self.relation {
+            Relation::Earlier => ordering == Ordering::Less,
+            Relation::EarlierOrEqual => {
+                ordering != Ordering::Greater
+            }
+            Relation::Exactly => ordering == Ordering::Equal,
+            Relation::LaterOrEqual => ordering != Ordering::Less,
+            Relation::Later => ordering == Ordering::Greater,
+        }
+    }
+}
+
+impl FromStr for VersionConstraint {
+    type Err = anyhow::Error;
+    /// `(>= 2.38)`または`>= 2.38`の形式を受け付けます。
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .trim();
+        let split_at = s
+            .find(|c: char| !matches!(c, '<' | '>' | '='))
+            .unwrap_or(s.len());
+        let (relation, version) = s.split_at(split_at);
+        Ok(Self {
+            relation: relation.parse()?,
+            version: version.trim().parse()?,
+        })
+    }
+}
+
+impl fmt::Display for VersionConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({} {})", self.relation, self.version)
+    }
+}
+
+impl Serialize for VersionConstraint {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionConstraint {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// `libfoo (>= 1.0)`のような、バージョンの制約付きの依存先
+///
+/// ipakのVersionRangeに変換すると制約の意味が変わりうるため、
+/// APTの依存関係はこちらを使ってdpkgの規則で判定します。
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct Dependency {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<VersionConstraint>,
+}
+
+impl Dependency {
+    /// `name`の`version`がこの依存先を満たすかどうかを返します。
+    pub fn satisfied_by(
+        &self,
+        name: &str,
+        version: &DebianVersion,
+    ) -> bool {
+        self.name == name
+            && self
+                .constraint
+                .as_ref()
+                .is_none_or(|c| c.satisfied_by(version))
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(constraint) = &self.constraint {
+            write!(f, " {}", constraint)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> DebianVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        let ordered = [
+            "1.0~rc1",
+            "1.0",
+            "1.0-1",
+            "1.0-1ubuntu1",
+            "1.0a",
+            "1.0+b1",
+            "1.1",
+            "2.10-3",
+            "2.10-3build2",
+            "2.38-1ubuntu6",
+            "1:0.1",
+            "1:2.38-1ubuntu6",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(
+                v(pair[0]) < v(pair[1]),
+                "{} < {}",
+                pair[0],
+                pair[1]
+            );
+        }
+        assert_eq!(v("1.0"), v("1.0-0"));
+        assert_eq!(v("0:1.01"), v("1.1"));
+        assert_eq!(
+            v("1:2.38-1ubuntu6").to_string(),
+            "1:2.38-1ubuntu6"
+        );
+        assert_eq!(v("1.0-rc-1").upstream, "1.0-rc");
+        assert!("1:".parse::<DebianVersion>().is_err());
+    }
+
+    #[test]
+    fn test_constraints() -> Result<()> {
+        let c: VersionConstraint = "(<< 2.9)".parse()?;
+        assert!(c.satisfied_by(&v("2.9~beta")));
+        assert!(!c.satisfied_by(&v("2.9")));
+        let c: VersionConstraint = ">= 2.38".parse()?;
+        assert!(c.satisfied_by(&v("2.38")));
+        assert!(c.satisfied_by(&v("1:1.0")));
+        assert!(!c.satisfied_by(&v("2.37-9")));
+        let c: VersionConstraint = "(= 1.0)".parse()?;
+        assert!(c.satisfied_by(&v("1.0-0")));
+        assert_eq!(c.to_string(), "(= 1.0)");
+        Ok(())
+    }
+}
@@ -2,3 +2,12 @@ use std::path::PathBuf;
 pub fn repo_list_path() -> PathBuf {
     PathBuf::from("/usr/ipm/repos.repo")
 }
+/// APTリポジトリの署名の検証に使う鍵束を置くディレクトリ
+pub fn trusted_keyrings_path() -> PathBuf {
+    PathBuf::from("/usr/ipm/trusted.gpg.d")
+}
+/// 変換されたパッケージのインストール状態を保存するディレクトリ
+/// (`pkg convert`で生成したipakのinstall.shと同じ場所)
+pub fn package_state_path(name: &str) -> PathBuf {
+    PathBuf::from("/var/lib/ipak/deb-compat").join(name)
+}
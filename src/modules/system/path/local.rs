@@ -4,6 +4,14 @@ use std::path::PathBuf;
 pub fn repo_list_path() -> PathBuf {
     ipm_dir().join("repos.repo")
 }
+/// APTリポジトリの署名の検証に使う鍵束を置くディレクトリ
+pub fn trusted_keyrings_path() -> PathBuf {
+    ipm_dir().join("trusted.gpg.d")
+}
+/// APTリポジトリから取得したインデックスのキャッシュを置くディレクトリ
+pub fn apt_cache_path() -> PathBuf {
+    ipm_dir().join("cache").join("apt")
+}
 fn home_dir() -> PathBuf {
     PathBuf::from(
         env::var("HOME")
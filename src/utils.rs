@@ -1 +1,6 @@
+pub mod compression;
+pub mod deb822;
+pub mod hash;
+#[cfg(test)]
+pub mod temp_dir;
 pub mod www;
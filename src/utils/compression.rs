@@ -0,0 +1,95 @@
+use bzip2::read::BzDecoder;
+use flate2::read::GzDecoder;
+use std::io::{self, Read};
+use std::path::Path;
+use xz2::read::XzDecoder;
+
+/// Supported compression formats for archives and indexes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Guesses the compression format from a file name suffix.
+    ///
+    /// Only the last path component is looked at, so that a dot in a
+    /// directory name (e.g. `Packages.diff/Index`) is not taken for
+    /// an unknown suffix.
+    pub fn from_name(name: &str) -> Result<Self, io::Error> {
+        let name = Path::new(name)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if name.ends_with(".gz") {
+            Ok(Self::Gzip)
+        } else if name.ends_with(".bz2") {
+            Ok(Self::Bzip2)
+        } else if name.ends_with(".xz") {
+            Ok(Self::Xz)
+        } else if name.ends_with(".zst") {
+            Ok(Self::Zstd)
+        } else if name.ends_with(".tar") || !name.contains('.') {
+            Ok(Self::None)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported compression: {}", name),
+            ))
+        }
+    }
+
+    /// Wraps `reader` with a decoder for this format.
+    pub fn decoder<'a, R: Read + 'a>(
+        self,
+        reader: R,
+    ) -> Result<Box<dyn Read + 'a>, io::Error> {
+        Ok(match self {
+            Self::None => Box::new(reader),
+            Self::Gzip => Box::new(GzDecoder::new(reader)),
+            Self::Bzip2 => Box::new(BzDecoder::new(reader)),
+            Self::Xz => Box::new(XzDecoder::new(reader)),
+            Self::Zstd => Box::new(zstd::Decoder::new(reader)?),
+        })
+    }
+}
+
+/// Returns a decoder for `reader` chosen from the suffix of `name`.
+pub fn decoder<'a, R: Read + 'a>(
+    name: &str,
+    reader: R,
+) -> Result<Box<dyn Read + 'a>, io::Error> {
+    Compression::from_name(name)?.decoder(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        for (name, expected) in [
+            ("main/binary-amd64/Packages.xz", Compression::Xz),
+            ("main/binary-amd64/Packages", Compression::None),
+            (
+                "main/binary-amd64/Packages.diff/Index",
+                Compression::None,
+            ),
+            (
+                "Packages.diff/T-2024-01-01-0000.00.gz",
+                Compression::Gzip,
+            ),
+            ("data.tar.zst", Compression::Zstd),
+        ] {
+            assert_eq!(
+                Compression::from_name(name).unwrap(),
+                expected
+            );
+        }
+        assert!(Compression::from_name("data.tar.lz4").is_err());
+    }
+}
@@ -0,0 +1,443 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// A single deb822 paragraph (a control file stanza, one entry of a
+/// `Packages` index, a `Release` file, ...).
+///
+/// Field order, multi-line values and comment lines are kept as they
+/// appear in the input, so a parsed paragraph serialises back to the
+/// same text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Paragraph {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Field { name: String, value: String },
+    Comment(String),
+}
+
+impl Paragraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses text containing exactly one paragraph.
+    pub fn parse(text: &str) -> Result<Self, io::Error> {
+        let mut paragraphs = parse(text)?;
+        match paragraphs.len() {
+            0 => Ok(Self::new()),
+            1 => Ok(paragraphs.remove(0)),
+            n => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Expected one paragraph, found {}", n),
+            )),
+        }
+    }
+
+    /// Returns the value of a field. Field names are case-insensitive.
+    ///
+    /// Continuation lines are joined with `\n` after removing the single
+    /// leading space or tab, so `" ."` becomes `"."`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Field { name: n, value }
+                if n.eq_ignore_ascii_case(name) =>
+            {
+                Some(value.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    /// Sets a field, replacing the value in place if it already exists
+    /// and appending it otherwise.
+    pub fn set(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        for line in &mut self.lines {
+            if let Line::Field { name: n, value: v } = line
+                && n.eq_ignore_ascii_case(name)
+            {
+                *v = value;
+                return;
+            }
+        }
+        self.lines
+            .push(Line::Field { name: name.to_string(), value });
+    }
+
+    /// Removes a field and returns its value.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let index =
+            self.lines.iter().position(|line| match line {
+                Line::Field { name: n, .. } => {
+                    n.eq_ignore_ascii_case(name)
+                }
+                Line::Comment(_) => false,
+            })?;
+        match self.lines.remove(index) {
+            Line::Field { value, .. } => Some(value),
+            Line::Comment(_) => None,
+        }
+    }
+
+    /// Iterates over `(name, value)` pairs in their original order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Field { name, value } => {
+                Some((name.as_str(), value.as_str()))
+            }
+            Line::Comment(_) => None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields().next().is_none()
+    }
+}
+
+impl fmt::Display for Paragraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Comment(comment) => {
+                    writeln!(f, "{}", comment)?
+                }
+                Line::Field { name, value } => {
+                    let mut value_lines = value.split('\n');
+                    let first =
+                        value_lines.next().unwrap_or_default();
+                    if first.is_empty() {
+                        writeln!(f, "{}:", name)?;
+                    } else {
+                        writeln!(f, "{}: {}", name, first)?;
+                    }
+                    for continuation in value_lines {
+                        writeln!(f, " {}", continuation)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses text made of paragraphs separated by blank lines.
+///
+/// Comment lines (starting with `#`) are kept with the paragraph that
+/// follows them.
+pub fn parse(text: &str) -> Result<Vec<Paragraph>, io::Error> {
+    let mut paragraphs = Vec::new();
+    let mut current = Paragraph::new();
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, message),
+            )
+        };
+        if line.trim().is_empty() {
+            if !current.lines.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+        } else if line.starts_with('#') {
+            current.lines.push(Line::Comment(line.to_string()));
+        } else if line.starts_with([' ', '\t']) {
+            let Some(Line::Field { value, .. }) =
+                current.lines.last_mut()
+            else {
+                return Err(error(
+                    "continuation line without a field",
+                ));
+            };
+            value.push('\n');
+            value.push_str(line[1..].trim_end());
+        } else {
+            let (name, value) =
+                line.split_once(':').ok_or_else(|| {
+                    error("expected 'Field: value'")
+                })?;
+            current.lines.push(Line::Field {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+            });
+        }
+    }
+    if !current.lines.is_empty() {
+        paragraphs.push(current);
+    }
+    Ok(paragraphs)
+}
+
+/// Serialises paragraphs, separating them with blank lines.
+pub fn to_string(paragraphs: &[Paragraph]) -> String {
+    paragraphs
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Read access to the fields of a [`Paragraph`] or a [`Stanza`].
+pub trait Fields {
+    /// Returns the value of a field, with continuation lines joined as
+    /// in [`Paragraph::get`]. Field names are case-insensitive.
+    fn field(&self, name: &str) -> Option<Cow<'_, str>>;
+}
+
+impl Fields for Paragraph {
+    fn field(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.get(name).map(Cow::Borrowed)
+    }
+}
+
+/// A paragraph whose field names and values borrow from the input text.
+///
+/// Reading one does not allocate per field, which matters for indexes
+/// with tens of thousands of paragraphs such as `Packages`. Comment
+/// lines are skipped, and multi-line values are only joined when read
+/// through [`Fields`].
+#[derive(Debug, Clone, Default)]
+pub struct Stanza<'a> {
+    fields: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Stanza<'a> {
+    /// Returns the value of a field as it appears in the input,
+    /// continuation lines included verbatim.
+    pub fn get_raw(&self, name: &str) -> Option<&'a str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+
+    /// Iterates over `(name, raw value)` pairs in their original order.
+    pub fn fields(
+        &self,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.fields.iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl Fields for Stanza<'_> {
+    fn field(&self, name: &str) -> Option<Cow<'_, str>> {
+        let value = self.get_raw(name)?;
+        if !value.contains('\n') {
+            return Some(Cow::Borrowed(value));
+        }
+        let mut lines = value.split('\n');
+        let mut joined =
+            lines.next().unwrap_or_default().to_string();
+        for line in lines {
+            joined.push('\n');
+            joined.push_str(
+                line.get(1..).unwrap_or_default().trim_end(),
+            );
+        }
+        Some(Cow::Owned(joined))
+    }
+}
+
+/// Iterator over the paragraphs of a text, created by [`stanzas`].
+pub struct Stanzas<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+/// Parses text made of paragraphs separated by blank lines, yielding
+/// [`Stanza`]s that borrow from `text`.
+pub fn stanzas(text: &str) -> Stanzas<'_> {
+    Stanzas { text, pos: 0, line: 0 }
+}
+
+impl<'a> Iterator for Stanzas<'a> {
+    type Item = Result<Stanza<'a>, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let text = self.text;
+        let mut stanza = Stanza::default();
+        // (name, start, end) of the field being read
+        let mut current: Option<(&'a str, usize, usize)> = None;
+        while self.pos < text.len() {
+            let start = self.pos;
+            let end = text[start..]
+                .find('\n')
+                .map_or(text.len(), |i| start + i);
+            self.pos = (end + 1).min(text.len());
+            self.line += 1;
+            let line = text[start..end].trim_end_matches('\r');
+            if line.trim().is_empty() {
+                if current.is_some() {
+                    break;
+                }
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with([' ', '\t']) {
+                match &mut current {
+                    Some((_, _, value_end)) => {
+                        *value_end =
+                            start + line.trim_end().len()
+                    }
+                    None => {
+                        self.pos = text.len();
+                        return Some(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "line {}: continuation line without a field",
+                                self.line
+                            ),
+                        )));
+                    }
+                }
+                continue;
+            }
+            let Some(colon) = line.find(':') else {
+                self.pos = text.len();
+                return Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "line {}: expected 'Field: value'",
+                        self.line
+                    ),
+                )));
+            };
+            if let Some((name, value_start, value_end)) = current
+            {
+                stanza
+                    .fields
+                    .push((name, &text[value_start..value_end]));
+            }
+            let value = &line[colon + 1..];
+            let value_start = start + colon + 1 + value.len()
+                - value.trim_start_matches([' ', '\t']).len();
+            current = Some((
+                line[..colon].trim(),
+                value_start,
+                (start + line.trim_end().len()).max(value_start),
+            ));
+        }
+        let (name, value_start, value_end) = current?;
+        stanza
+            .fields
+            .push((name, &text[value_start..value_end]));
+        Some(Ok(stanza))
+    }
+}
+
+/// Reads the next paragraph from `reader` into `buf`, so that a large
+/// index can be parsed with [`stanzas`] one paragraph at a time
+/// without holding the whole text in memory.
+///
+/// Blank lines before the paragraph are skipped; `buf` is cleared
+/// first. Returns `false` when the input is exhausted. Invalid UTF-8
+/// is replaced as in [`String::from_utf8_lossy`].
+pub fn read_stanza<R: BufRead>(
+    reader: &mut R,
+    buf: &mut String,
+) -> Result<bool, io::Error> {
+    buf.clear();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(!buf.is_empty());
+        }
+        let text = String::from_utf8_lossy(&line);
+        if text.trim().is_empty() {
+            if buf.is_empty() {
+                continue;
+            }
+            return Ok(true);
+        }
+        buf.push_str(&text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), io::Error> {
+        let text = "# main archive
+Types: deb
+URIs: http://archive.ubuntu.com/ubuntu/
+Suites: noble
+Components: main
+
+Package: hello
+Version: 2.10-3build2
+Description: example package based on GNU hello
+ The GNU hello program produces a familiar, friendly greeting.
+ .
+ Seriously, though: this is an example.
+SHA256:
+ 0123 104 main/binary-amd64/Packages
+";
+        let paragraphs = parse(text)?;
+        assert_eq!(paragraphs.len(), 2);
+        assert_eq!(to_string(&paragraphs), text);
+
+        let hello = &paragraphs[1];
+        assert_eq!(
+            hello.get("description").unwrap(),
+            "example package based on GNU hello\n\
+The GNU hello program produces a familiar, friendly greeting.\n\
+.\n\
+Seriously, though: this is an example."
+        );
+        assert_eq!(
+            hello
+                .fields()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>(),
+            ["Package", "Version", "Description", "SHA256"]
+        );
+
+        // Borrowed stanzas read the same values
+        let borrowed = stanzas(text)
+            .collect::<Result<Vec<Stanza>, io::Error>>()?;
+        assert_eq!(borrowed.len(), 2);
+        for (stanza, paragraph) in
+            borrowed.iter().zip(&paragraphs)
+        {
+            for (name, value) in paragraph.fields() {
+                assert_eq!(stanza.field(name).unwrap(), value);
+            }
+        }
+        assert!(
+            borrowed[1]
+                .get_raw("Description")
+                .unwrap()
+                .contains("\n .")
+        );
+        assert!(
+            stanzas("Package: a\n bad\nbroken\n")
+                .any(|s| s.is_err())
+        );
+
+        // Reading paragraph by paragraph gives the same stanzas
+        let mut reader = text.as_bytes();
+        let mut buf = String::new();
+        for paragraph in &paragraphs {
+            assert!(read_stanza(&mut reader, &mut buf)?);
+            let stanza = stanzas(&buf).next().unwrap()?;
+            for (name, value) in paragraph.fields() {
+                assert_eq!(stanza.field(name).unwrap(), value);
+            }
+        }
+        assert!(!read_stanza(&mut reader, &mut buf)?);
+        Ok(())
+    }
+}
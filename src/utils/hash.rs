@@ -0,0 +1,44 @@
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+/// Returns the SHA-256 digest of `data` as lowercase hex, the form used
+/// by APT indexes.
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// Formats a digest as lowercase hex.
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A reader that computes the SHA-256 digest and size of everything
+/// read through it, so that a download can be verified while it is
+/// being decompressed.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, hasher: Sha256::new(), size: 0 }
+    }
+
+    /// Reads the rest of the input and returns the digest as lowercase
+    /// hex together with the total size.
+    pub fn finish(mut self) -> io::Result<(String, u64)> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok((to_hex(&self.hasher.finalize()), self.size))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+}
@@ -41,19 +41,33 @@ impl URL {
         &self,
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let request_url = self.to_string();
-        let response = reqwest::blocking::get(&request_url)?;
+        let response = reqwest::blocking::get(&request_url)?
+            .error_for_status()?;
         response
             .bytes()
             .map(|b| b.to_vec())
             .map_err(|e| e.into())
     }
+    /// Opens the URL and returns the response body as a reader, so
+    /// that large files can be processed while they are downloaded.
+    pub fn open(
+        &self,
+    ) -> Result<
+        impl std::io::Read + use<>,
+        Box<dyn std::error::Error>,
+    > {
+        let request_url = self.to_string();
+        Ok(reqwest::blocking::get(&request_url)?
+            .error_for_status()?)
+    }
     /// Fetches data from the URL.
     /// Returns the response body as a String or a boxed error.
     pub fn fetch(
         &self,
     ) -> Result<String, Box<dyn std::error::Error>> {
         let request_url = self.to_string();
-        let response = reqwest::blocking::get(&request_url)?; // Use ? for error propagation
+        let response = reqwest::blocking::get(&request_url)?
+            .error_for_status()?; // Use ? for error propagation
 
         response.text().map_err(|e| e.into()) // Convert reqwest::Error to Box<dyn std::error::Error>
     }